@@ -1,22 +1,49 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::UnorderedMap;
+use near_sdk::collections::{UnorderedMap, UnorderedSet};
+use near_sdk::json_types::{Base58PublicKey, Base64VecU8, U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
-    env, ext_contract, log, near_bindgen, AccountId, Balance, Gas, PanicOnDefault, Promise,
-    PromiseResult,
+    assert_one_yocto, env, ext_contract, log, near_bindgen, AccountId, Balance, Gas,
+    PanicOnDefault, Promise, PromiseResult, Timestamp,
 };
 
+use crate::migrate::ContractV0;
+
+mod migrate;
+#[cfg(test)]
+mod tests;
+
 near_sdk::setup_alloc!();
 const CODE: &[u8] = include_bytes!("../../ticket/res/contract.wasm");
-const INITIAL_BALANCE: Balance = 3_500_000_000_000_000_000_000_000;
-const CREATE_CONTRACT_FEE: Balance = 5_000_000_000_000_000_000_000_000;
-const PREPARE_GAS: Gas = 25_000_000_000_000;
+// Version of the ticket code in CODE, given to the contracts it is deployed to, which report
+// it in `contract_version`. Bump it with the ticket crate version when rebuilding CODE.
+pub const CONTRACT_VERSION: &str = "0.1.0";
+const DEFAULT_INITIAL_BALANCE: Balance = 3_500_000_000_000_000_000_000_000;
+const DEFAULT_CREATE_CONTRACT_FEE: Balance = 5_000_000_000_000_000_000_000_000;
+const DEFAULT_PREPARE_GAS: Gas = 25_000_000_000_000;
+// `new` of a ticket contract does not run with less
+const MIN_PREPARE_GAS: Gas = 10_000_000_000_000;
+// account and state `new` writes, on top of CODE
+const NEW_CONTRACT_STORAGE: u64 = 1_000;
+const MAX_BATCH_SIZE: u64 = 100;
+const UPGRADE_GAS: Gas = 150_000_000_000_000;
+const DELETE_GAS: Gas = 20_000_000_000_000;
+const CHECK_DELETE_GAS: Gas = 10_000_000_000_000;
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
     pub owner_id: AccountId,
     pub ticket_contracts_by_owner: UnorderedMap<AccountId, Vec<AccountId>>,
     pub ticket_contracts: Vec<AccountId>,
+    pub default_icon: Option<String>,
+    pub deploy_modes: UnorderedMap<AccountId, DeployMode>,
+    pub all_contracts: UnorderedSet<AccountId>,
+    pub initial_balance: Balance,
+    pub create_contract_fee: Balance,
+    pub prepare_gas: Gas,
+    pub reserved_accounts: UnorderedSet<AccountId>, // created or being created
+    // created by the first factory, their code has no `upgrade` nor `delete_self`
+    pub first_version_contracts: UnorderedSet<AccountId>,
 }
 
 #[near_bindgen]
@@ -27,19 +54,131 @@ impl Contract {
             owner_id,
             ticket_contracts_by_owner: UnorderedMap::new(b"ticket_contract_by_owner".to_vec()),
             ticket_contracts: Vec::new(),
+            default_icon: None,
+            deploy_modes: UnorderedMap::new(b"deploy_modes".to_vec()),
+            all_contracts: UnorderedSet::new(b"all_contracts".to_vec()),
+            initial_balance: DEFAULT_INITIAL_BALANCE,
+            create_contract_fee: DEFAULT_CREATE_CONTRACT_FEE,
+            prepare_gas: DEFAULT_PREPARE_GAS,
+            reserved_accounts: UnorderedSet::new(b"reserved_accounts".to_vec()),
+            first_version_contracts: UnorderedSet::new(b"first_version_contracts".to_vec()),
         }
     }
+
+    /// Convert the state of the previous version, call it right after deploying new code
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let old: ContractV0 =
+            env::state_read().unwrap_or_else(|| env::panic(b"No state to migrate"));
+        old.into()
+    }
+
+    /// Ticket image used by new contracts whose metadata has no icon
+    pub fn set_default_icon(&mut self, icon: Option<String>) {
+        self.assert_owner();
+        self.default_icon = icon;
+    }
+
+    pub fn get_default_icon(&self) -> Option<String> {
+        self.default_icon.clone()
+    }
+
+    /// Balance a new ticket contract starts with, it pays for the contract storage
+    pub fn set_initial_balance(&mut self, initial_balance: U128) {
+        self.assert_owner();
+        let min_balance = Self::min_initial_balance();
+        assert!(
+            initial_balance.0 >= min_balance,
+            "Initial balance must be at least {} to pay for the contract storage",
+            min_balance
+        );
+        self.initial_balance = initial_balance.0;
+    }
+
+    /// Storage cost of a new ticket contract
+    fn min_initial_balance() -> Balance {
+        (CODE.len() as u64 + NEW_CONTRACT_STORAGE) as Balance * env::storage_byte_cost()
+    }
+
+    pub fn set_create_contract_fee(&mut self, fee: U128) {
+        self.assert_owner();
+        self.create_contract_fee = fee.0;
+    }
+
+    /// Gas for the `new` call of a new ticket contract
+    pub fn set_prepare_gas(&mut self, gas: U64) {
+        self.assert_owner();
+        assert!(gas.0 >= MIN_PREPARE_GAS, "Prepare gas must be at least {}", MIN_PREPARE_GAS);
+        self.prepare_gas = gas.0;
+    }
+
+    /// Deposit `create_new_ticket_contract` requires
+    pub fn get_create_contract_deposit(&self) -> U128 {
+        U128(self.create_contract_fee + self.initial_balance)
+    }
+    /// The prefix must be one account id label: lowercase letters and digits, separated by
+    /// single '-' or '_', keeping the whole sub account id at most 64 bytes long
+    fn assert_valid_prefix(prefix: &str) {
+        let max_len = 63usize.saturating_sub(env::current_account_id().len());
+        assert!(
+            !prefix.is_empty() && prefix.len() <= max_len,
+            "Prefix must be 1 to {} characters long",
+            max_len
+        );
+        let bytes = prefix.as_bytes();
+        let is_separator = |byte: &u8| *byte == b'-' || *byte == b'_';
+        assert!(
+            bytes
+                .iter()
+                .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || is_separator(byte))
+                && !is_separator(&bytes[0])
+                && !is_separator(&bytes[bytes.len() - 1])
+                && !bytes.windows(2).any(|pair| is_separator(&pair[0]) && is_separator(&pair[1])),
+            "Prefix {} is not a valid account id label",
+            prefix
+        );
+    }
+
+    fn assert_not_first_version(&self, contract_id: &AccountId) {
+        assert!(
+            !self.first_version_contracts.contains(contract_id),
+            "Contract {} runs the first ticket code, the factory can not manage it",
+            contract_id
+        );
+    }
+
+    fn assert_owner(&self) {
+        assert!(
+            env::predecessor_account_id() == self.owner_id,
+            "Caller {} is not owner: {}",
+            env::predecessor_account_id(),
+            self.owner_id
+        );
+    }
+
     #[payable]
     pub fn create_new_ticket_contract(
         &mut self,
         prefix: String,
         metadata: TicketContractMetadata,
+        deploy_mode: Option<DeployMode>, // default Locked
     ) -> Promise {
         assert!(
-            env::attached_deposit() == CREATE_CONTRACT_FEE + INITIAL_BALANCE,
+            env::attached_deposit() == self.create_contract_fee + self.initial_balance,
             "Please deposit exactly contract creation fee"
         );
+        let mut metadata = metadata;
+        if metadata.icon.is_none() {
+            metadata.icon = self.default_icon.clone();
+        }
+        Self::assert_valid_prefix(&prefix);
         let subaccount_id = format!("{}.{}", prefix, env::current_account_id());
+        assert!(
+            self.reserved_accounts.insert(&subaccount_id),
+            "Account {} is already taken",
+            subaccount_id
+        );
         log!(
             "{}",
             format!("Creating new ticket contract at account {}", subaccount_id)
@@ -47,34 +186,108 @@ impl Contract {
         let mut ticket_contracts = self
             .ticket_contracts_by_owner
             .get(&env::predecessor_account_id())
-            .unwrap_or_else(|| Vec::new());
+            .unwrap_or_default();
         ticket_contracts.push(subaccount_id.clone());
         self.ticket_contracts_by_owner
             .insert(&env::predecessor_account_id(), &ticket_contracts);
 
         self.ticket_contracts.push(subaccount_id.clone());
 
-        Promise::new(subaccount_id.clone())
+        let deploy_mode = deploy_mode.unwrap_or(DeployMode::Locked);
+        self.deploy_modes.insert(&subaccount_id, &deploy_mode);
+        let mut promise = Promise::new(subaccount_id.clone())
             .create_account()
-            .transfer(INITIAL_BALANCE)
-            .add_full_access_key(env::signer_account_pk())
+            .transfer(self.initial_balance);
+        if let DeployMode::FullAccessKey(public_key) = deploy_mode {
+            promise = promise.add_full_access_key(public_key.into());
+        }
+        promise
             .deploy_contract(CODE.to_vec())
             .then(new_ticket_contract::new(
                 env::predecessor_account_id(),
                 metadata,
+                Some(env::current_account_id()),
+                Some(env::block_timestamp()),
+                Some(CONTRACT_VERSION.to_string()),
                 &subaccount_id,
                 0,
-                PREPARE_GAS,
+                self.prepare_gas,
             ))
             .then(ex_self::check_create_new_contract(
                 env::predecessor_account_id(),
+                subaccount_id,
+                U128(env::attached_deposit()),
                 &env::current_account_id(),
                 0,
                 5_000_000_000_000,
             ))
     }
+    /// Delete a ticket contract and send its balance to `beneficiary`. The ticket contract
+    /// checks the caller is its current owner, who may not be the account that created it.
+    #[payable]
+    pub fn delete_contract(&mut self, contract_id: AccountId, beneficiary: AccountId) -> Promise {
+        assert_one_yocto();
+        assert!(
+            self.all_contracts.contains(&contract_id),
+            "Contract {} was not created by this factory",
+            contract_id
+        );
+        self.assert_not_first_version(&contract_id);
+        new_ticket_contract::delete_self(
+            beneficiary,
+            env::predecessor_account_id(),
+            &contract_id,
+            0,
+            DELETE_GAS,
+        )
+        .then(ex_self::check_delete_contract(
+            contract_id,
+            &env::current_account_id(),
+            0,
+            CHECK_DELETE_GAS,
+        ))
+    }
+
     #[private]
-    pub fn check_create_new_contract(&self, creater_account: AccountId) {
+    pub fn check_delete_contract(&mut self, contract_id: AccountId) {
+        if env::promise_result(0) == PromiseResult::Failed {
+            log!("Fail to delete ticket contract {}", contract_id);
+        } else {
+            let creator_id = self
+                .ticket_contracts_by_owner
+                .iter()
+                .find(|(_, contract_ids)| contract_ids.contains(&contract_id))
+                .map(|(creator_id, _)| creator_id)
+                .unwrap_or_default();
+            self.unregister_contract(&creator_id, &contract_id);
+            log!("Ticket contract {} is deleted", contract_id);
+        }
+    }
+
+    fn unregister_contract(&mut self, owner_id: &AccountId, contract_id: &AccountId) {
+        self.all_contracts.remove(contract_id);
+        self.reserved_accounts.remove(contract_id);
+        let mut ticket_contracts = self
+            .ticket_contracts_by_owner
+            .get(owner_id)
+            .unwrap_or_default();
+        ticket_contracts.retain(|account_id| account_id != contract_id);
+        if ticket_contracts.is_empty() {
+            self.ticket_contracts_by_owner.remove(owner_id);
+        } else {
+            self.ticket_contracts_by_owner.insert(owner_id, &ticket_contracts);
+        }
+        self.ticket_contracts.retain(|account_id| account_id != contract_id);
+        self.deploy_modes.remove(contract_id);
+    }
+
+    #[private]
+    pub fn check_create_new_contract(
+        &mut self,
+        creater_account: AccountId,
+        contract_id: AccountId,
+        deposit: U128,
+    ) {
         let mut result: bool = true;
         for i in 0..env::promise_results_count() {
             if env::promise_result(i) == PromiseResult::Failed {
@@ -82,29 +295,98 @@ impl Contract {
                 break;
             }
         }
-        if result == false {
+        if !result {
             log!("Fail to create new ticket contract");
-            Promise::new(creater_account).transfer(INITIAL_BALANCE + CREATE_CONTRACT_FEE);
+            self.unregister_contract(&creater_account, &contract_id);
+            Promise::new(creater_account).transfer(deposit.0);
+        } else {
+            self.all_contracts.insert(&contract_id);
         }
     }
     pub fn get_contracts_by_owner(&self, owner_id: AccountId) -> Vec<AccountId> {
         self.ticket_contracts_by_owner
             .get(&owner_id)
-            .unwrap_or_else(|| Vec::new())
+            .unwrap_or_default()
     }
 
     pub fn get_ticket_contracts(&self) -> Vec<AccountId> {
         self.ticket_contracts.clone()
     }
+
+    /// Deploy the ticket code of this factory to one of its contracts. With `migrate` the
+    /// contract converts its state with the new code's `migrate`. Contracts of the first
+    /// factory have no `upgrade` method and are not upgraded.
+    pub fn upgrade_contract(&mut self, contract_id: AccountId, migrate: bool) -> Promise {
+        self.assert_owner();
+        assert!(
+            self.all_contracts.contains(&contract_id),
+            "Contract {} was not created by this factory",
+            contract_id
+        );
+        self.assert_not_first_version(&contract_id);
+        log!("Upgrade ticket contract {}", contract_id);
+        new_ticket_contract::upgrade(
+            Base64VecU8(CODE.to_vec()),
+            migrate,
+            CONTRACT_VERSION.to_string(),
+            &contract_id,
+            0,
+            UPGRADE_GAS,
+        )
+    }
+
+    /// Version of the ticket code new contracts get, see `contract_version` of a ticket contract
+    pub fn embedded_contract_version(&self) -> String {
+        CONTRACT_VERSION.to_string()
+    }
+
+    /// Ticket contracts that were created successfully
+    pub fn get_all_contracts(&self, from_index: u64, limit: u64) -> Vec<AccountId> {
+        self.all_contracts
+            .iter()
+            .skip(from_index as usize)
+            .take(limit.min(MAX_BATCH_SIZE) as usize)
+            .collect()
+    }
+
+    pub fn contracts_count(&self) -> u64 {
+        self.all_contracts.len()
+    }
+
+    pub fn get_deploy_mode(&self, contract_id: AccountId) -> Option<DeployMode> {
+        self.deploy_modes.get(&contract_id)
+    }
 }
 
-#[ext_contract(new_ticket_contract)]
-trait TTicketContract {
-    fn new(owner_id: AccountId, metadata: TicketContractMetadata) -> Self;
+// the generated call builders take the account, deposit and gas on top of the arguments
+#[allow(clippy::too_many_arguments)]
+mod ticket_calls {
+    use super::*;
+
+    #[ext_contract(new_ticket_contract)]
+    trait TTicketContract {
+        fn new(
+            owner_id: AccountId,
+            metadata: TicketContractMetadata,
+            factory_id: Option<AccountId>,
+            deployed_at: Option<Timestamp>,
+            code_version: Option<String>,
+        ) -> Self;
+        fn upgrade(&mut self, code: Base64VecU8, migrate: bool, code_version: String) -> Promise;
+        fn delete_self(&mut self, beneficiary: AccountId, requester: AccountId) -> Promise;
+    }
 }
+use ticket_calls::new_ticket_contract;
+
 #[ext_contract(ex_self)]
 trait TContractSelf {
-    fn check_create_new_contract(&mut self, creater_account: AccountId);
+    fn check_create_new_contract(
+        &mut self,
+        creater_account: AccountId,
+        contract_id: AccountId,
+        deposit: U128,
+    );
+    fn check_delete_contract(&mut self, contract_id: AccountId);
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -114,4 +396,16 @@ pub struct TicketContractMetadata {
     pub name: String,   // required, ex. "Mosaics"
     pub symbol: String, // required, ex. "MOSIAC"
     pub description: Option<String>,
+    pub icon: Option<String>, // fallback media of minted tickets
+    #[serde(default)]
+    pub base_uri: Option<String>, // gateway for relative `media` and `reference` links
+}
+
+/// Keys of a new ticket contract. A `Locked` contract has no access key, so its code can not
+/// be changed by anyone holding a key.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum DeployMode {
+    Locked,
+    FullAccessKey(Base58PublicKey),
 }
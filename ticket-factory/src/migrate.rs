@@ -0,0 +1,39 @@
+//! Earlier layouts of the factory state, read by `Contract::migrate` after an upgrade.
+use crate::*;
+
+/// State of the first factory, before the registry and the configurable fees
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV0 {
+    pub(crate) owner_id: AccountId,
+    pub(crate) ticket_contracts_by_owner: UnorderedMap<AccountId, Vec<AccountId>>,
+    pub(crate) ticket_contracts: Vec<AccountId>,
+}
+
+impl From<ContractV0> for Contract {
+    fn from(old: ContractV0) -> Self {
+        let mut all_contracts = UnorderedSet::new(b"all_contracts".to_vec());
+        let mut reserved_accounts = UnorderedSet::new(b"reserved_accounts".to_vec());
+        let mut first_version_contracts =
+            UnorderedSet::new(b"first_version_contracts".to_vec());
+        // the first factory did not tell failed creations apart, the contracts it
+        // recorded are all taken to exist
+        for contract_id in old.ticket_contracts.iter() {
+            all_contracts.insert(contract_id);
+            reserved_accounts.insert(contract_id);
+            first_version_contracts.insert(contract_id);
+        }
+        Self {
+            owner_id: old.owner_id,
+            ticket_contracts_by_owner: old.ticket_contracts_by_owner,
+            ticket_contracts: old.ticket_contracts,
+            default_icon: None,
+            deploy_modes: UnorderedMap::new(b"deploy_modes".to_vec()),
+            all_contracts,
+            initial_balance: DEFAULT_INITIAL_BALANCE,
+            create_contract_fee: DEFAULT_CREATE_CONTRACT_FEE,
+            prepare_gas: DEFAULT_PREPARE_GAS,
+            reserved_accounts,
+            first_version_contracts,
+        }
+    }
+}
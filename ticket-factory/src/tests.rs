@@ -0,0 +1,387 @@
+use crate::*;
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+use near_sdk::{testing_env, MockedBlockchain};
+
+// accounts(0) is the factory, accounts(1) its owner and the others operators
+
+fn context(predecessor: ValidAccountId) -> VMContextBuilder {
+    let mut builder = VMContextBuilder::new();
+    builder
+        .current_account_id(accounts(0))
+        .signer_account_id(predecessor.clone())
+        .predecessor_account_id(predecessor);
+    builder
+}
+
+fn setup() -> Contract {
+    testing_env!(context(accounts(1)).build());
+    Contract::new(accounts(1).into())
+}
+
+fn metadata() -> TicketContractMetadata {
+    TicketContractMetadata {
+        spec: "nft-1.0.0".to_string(),
+        name: "Tickets".to_string(),
+        symbol: "TKT".to_string(),
+        description: None,
+        icon: None,
+        base_uri: None,
+    }
+}
+
+fn create(contract: &mut Contract, operator: ValidAccountId, prefix: &str) -> AccountId {
+    let deposit = contract.get_create_contract_deposit().0;
+    testing_env!(context(operator).attached_deposit(deposit).build());
+    contract.create_new_ticket_contract(prefix.to_string(), metadata(), None);
+    format!("{}.{}", prefix, env::current_account_id())
+}
+
+/// The `check_create_new_contract` callback after the creation resolved to `result`
+fn finish_create(
+    contract: &mut Contract,
+    operator: ValidAccountId,
+    contract_id: &str,
+    result: PromiseResult,
+) {
+    let deposit = contract.get_create_contract_deposit();
+    testing_env!(
+        context(accounts(0)).build(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        vec![result]
+    );
+    contract.check_create_new_contract(operator.into(), contract_id.to_string(), deposit);
+}
+
+fn create_contract(contract: &mut Contract, operator: ValidAccountId, prefix: &str) -> AccountId {
+    let contract_id = create(contract, operator.clone(), prefix);
+    finish_create(contract, operator, &contract_id, PromiseResult::Successful(vec![]));
+    contract_id
+}
+
+/// (receiver, amount) of the transfers scheduled since the last `testing_env!`
+fn transfers() -> Vec<(AccountId, Balance)> {
+    get_created_receipts()
+        .iter()
+        .flat_map(|receipt| {
+            let receipt = format!("{:?}", receipt);
+            let receiver = receipt.split("receiver_id: \"").nth(1).unwrap();
+            let receiver = receiver.split('"').next().unwrap().to_string();
+            receipt
+                .split("Transfer(TransferAction { deposit: ")
+                .skip(1)
+                .map(|rest| (receiver.clone(), rest.split(' ').next().unwrap().parse().unwrap()))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn receivers() -> Vec<String> {
+    get_created_receipts()
+        .iter()
+        .map(|receipt| {
+            let receipt = format!("{:?}", receipt);
+            let receiver = receipt.split("receiver_id: \"").nth(1).unwrap();
+            receiver.split('"').next().unwrap().to_string()
+        })
+        .collect()
+}
+
+/// Whether the receipts scheduled since the last `testing_env!` carry `bytes`, method names
+/// and arguments show as byte lists
+fn receipts_contain(bytes: &[u8]) -> bool {
+    let bytes = format!("{:?}", bytes);
+    format!("{:?}", get_created_receipts()).contains(bytes.trim_matches(|c| c == '[' || c == ']'))
+}
+
+#[test]
+fn created_contracts_are_registered_once_deployed() {
+    let mut contract = setup();
+    let contract_id = create(&mut contract, accounts(2), "show");
+    assert_eq!(contract_id, "show.alice");
+    assert_eq!(receivers(), vec!["show.alice", "show.alice", "alice"]);
+    assert_eq!(contract.contracts_count(), 0);
+    finish_create(&mut contract, accounts(2), &contract_id, PromiseResult::Successful(vec![]));
+    create_contract(&mut contract, accounts(3), "gig");
+    assert_eq!(contract.contracts_count(), 2);
+    assert_eq!(contract.get_all_contracts(0, 10), vec!["show.alice", "gig.alice"]);
+    assert_eq!(contract.get_all_contracts(1, 10), vec!["gig.alice"]);
+    assert_eq!(contract.get_contracts_by_owner(accounts(2).into()), vec!["show.alice"]);
+    assert_eq!(contract.get_ticket_contracts(), vec!["show.alice", "gig.alice"]);
+    assert_eq!(contract.get_deploy_mode(contract_id), Some(DeployMode::Locked));
+}
+
+#[test]
+fn failed_creations_are_rolled_back_and_refunded() {
+    let mut contract = setup();
+    let contract_id = create(&mut contract, accounts(2), "show");
+    assert_eq!(contract.get_contracts_by_owner(accounts(2).into()), vec!["show.alice"]);
+    finish_create(&mut contract, accounts(2), &contract_id, PromiseResult::Failed);
+    let deposit = DEFAULT_CREATE_CONTRACT_FEE + DEFAULT_INITIAL_BALANCE;
+    assert_eq!(transfers(), vec![(accounts(2).into(), deposit)]);
+    assert!(contract.get_contracts_by_owner(accounts(2).into()).is_empty());
+    assert!(contract.get_ticket_contracts().is_empty());
+    assert_eq!(contract.contracts_count(), 0);
+    assert_eq!(contract.get_deploy_mode(contract_id), None);
+    // the prefix is free again
+    create_contract(&mut contract, accounts(3), "show");
+    assert_eq!(contract.get_contracts_by_owner(accounts(3).into()), vec!["show.alice"]);
+}
+
+#[test]
+fn fees_and_gas_are_configurable() {
+    let mut contract = setup();
+    let initial_balance = Contract::min_initial_balance();
+    contract.set_initial_balance(U128(initial_balance));
+    contract.set_create_contract_fee(U128(6));
+    contract.set_prepare_gas(U64(30_000_000_000_000));
+    assert_eq!(contract.get_create_contract_deposit(), U128(initial_balance + 6));
+    assert_eq!(contract.prepare_gas, 30_000_000_000_000);
+    create(&mut contract, accounts(2), "show");
+    assert_eq!(transfers(), vec![("show.alice".to_string(), initial_balance)]);
+}
+
+#[test]
+#[should_panic(expected = "Initial balance must be at least")]
+fn the_initial_balance_pays_for_the_contract_storage() {
+    let mut contract = setup();
+    contract.set_initial_balance(U128(Contract::min_initial_balance() - 1));
+}
+
+#[test]
+#[should_panic(expected = "Prepare gas must be at least 10000000000000")]
+fn prepare_gas_is_enough_for_new() {
+    let mut contract = setup();
+    contract.set_prepare_gas(U64(0));
+}
+
+#[test]
+fn contracts_are_paged() {
+    let mut contract = setup();
+    for index in 0..MAX_BATCH_SIZE + 1 {
+        contract.all_contracts.insert(&format!("show{}.alice", index));
+    }
+    assert_eq!(contract.get_all_contracts(0, u64::MAX).len() as u64, MAX_BATCH_SIZE);
+    assert_eq!(contract.get_all_contracts(MAX_BATCH_SIZE, 10), vec!["show100.alice"]);
+}
+
+#[test]
+#[should_panic(expected = "Please deposit exactly contract creation fee")]
+fn the_deposit_must_match_the_configured_fees() {
+    let mut contract = setup();
+    contract.set_create_contract_fee(U128(6));
+    testing_env!(context(accounts(2)).attached_deposit(DEFAULT_CREATE_CONTRACT_FEE).build());
+    contract.create_new_ticket_contract("show".to_string(), metadata(), None);
+}
+
+#[test]
+#[should_panic(expected = "Caller charlie is not owner: bob")]
+fn only_the_owner_sets_the_fees() {
+    let mut contract = setup();
+    testing_env!(context(accounts(2)).build());
+    contract.set_create_contract_fee(U128(6));
+}
+
+#[test]
+fn registered_contracts_are_upgraded() {
+    let mut contract = setup();
+    let contract_id = create_contract(&mut contract, accounts(2), "show");
+    testing_env!(context(accounts(1)).build());
+    contract.upgrade_contract(contract_id.clone(), true);
+    assert_eq!(receivers(), vec![contract_id]);
+    assert!(receipts_contain(b"upgrade"));
+    assert!(receipts_contain(br#""migrate":true"#));
+    let code = near_sdk::serde_json::to_string(&Base64VecU8(CODE.to_vec())).unwrap();
+    assert!(receipts_contain(format!(r#""code":{}"#, code).as_bytes()));
+}
+
+#[test]
+#[should_panic(expected = "Contract show.alice runs the first ticket code")]
+fn contracts_of_the_first_factory_are_not_upgraded() {
+    let mut contract = setup();
+    contract.all_contracts.insert(&"show.alice".to_string());
+    contract.first_version_contracts.insert(&"show.alice".to_string());
+    contract.upgrade_contract("show.alice".to_string(), true);
+}
+
+#[test]
+#[should_panic(expected = "Contract other.near was not created by this factory")]
+fn other_contracts_are_not_upgraded() {
+    let mut contract = setup();
+    contract.upgrade_contract("other.near".to_string(), true);
+}
+
+#[test]
+#[should_panic(expected = "Contract show.alice was not created by this factory")]
+fn contracts_being_created_are_not_upgraded() {
+    let mut contract = setup();
+    create(&mut contract, accounts(2), "show");
+    testing_env!(context(accounts(1)).build());
+    contract.upgrade_contract("show.alice".to_string(), false);
+}
+
+#[test]
+#[should_panic(expected = "Caller charlie is not owner: bob")]
+fn only_the_owner_upgrades() {
+    let mut contract = setup();
+    let contract_id = create_contract(&mut contract, accounts(2), "show");
+    testing_env!(context(accounts(2)).build());
+    contract.upgrade_contract(contract_id, false);
+}
+
+#[test]
+fn contracts_are_deployed_with_the_embedded_version() {
+    let mut contract = setup();
+    assert_eq!(contract.embedded_contract_version(), CONTRACT_VERSION);
+    create(&mut contract, accounts(2), "show");
+    let version = format!(r#""code_version":"{}""#, contract.embedded_contract_version());
+    assert!(receipts_contain(version.as_bytes()));
+    let contract_id = create_contract(&mut contract, accounts(2), "gig");
+    testing_env!(context(accounts(1)).build());
+    contract.upgrade_contract(contract_id, false);
+    assert!(receipts_contain(version.as_bytes()));
+}
+
+#[test]
+fn the_embedded_version_is_the_ticket_crate_version() {
+    let manifest = include_str!("../../ticket/Cargo.toml");
+    let package = manifest.split("[dependencies]").next().unwrap();
+    assert!(package.contains(&format!("\nversion = \"{}\"", CONTRACT_VERSION)));
+}
+
+#[test]
+fn operators_delete_their_contracts() {
+    let mut contract = setup();
+    let contract_id = create_contract(&mut contract, accounts(2), "show");
+    testing_env!(context(accounts(2)).attached_deposit(1).build());
+    contract.delete_contract(contract_id.clone(), accounts(2).into());
+    assert_eq!(receivers(), vec![contract_id.clone(), "alice".to_string()]);
+    testing_env!(
+        context(accounts(0)).build(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        vec![PromiseResult::Failed]
+    );
+    contract.check_delete_contract(contract_id.clone());
+    assert_eq!(contract.contracts_count(), 1);
+    testing_env!(
+        context(accounts(0)).build(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        vec![PromiseResult::Successful(vec![])]
+    );
+    contract.check_delete_contract(contract_id);
+    assert_eq!(contract.contracts_count(), 0);
+    assert!(contract.get_contracts_by_owner(accounts(2).into()).is_empty());
+    assert!(contract.get_ticket_contracts().is_empty());
+    // the account can be created again
+    create_contract(&mut contract, accounts(3), "show");
+}
+
+#[test]
+fn the_current_owner_deletes_a_transferred_contract() {
+    let mut contract = setup();
+    let contract_id = create_contract(&mut contract, accounts(2), "show");
+    // the ticket contract checks danny owns it now
+    testing_env!(context(accounts(3)).attached_deposit(1).build());
+    contract.delete_contract(contract_id.clone(), accounts(3).into());
+    assert_eq!(receivers(), vec![contract_id.clone(), "alice".to_string()]);
+    testing_env!(
+        context(accounts(0)).build(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        vec![PromiseResult::Successful(vec![])]
+    );
+    contract.check_delete_contract(contract_id);
+    assert!(contract.get_contracts_by_owner(accounts(2).into()).is_empty());
+    assert_eq!(contract.contracts_count(), 0);
+}
+
+#[test]
+#[should_panic(expected = "Contract show.alice was not created by this factory")]
+fn contracts_being_created_are_not_deleted() {
+    let mut contract = setup();
+    create(&mut contract, accounts(2), "show");
+    testing_env!(context(accounts(2)).attached_deposit(1).build());
+    contract.delete_contract("show.alice".to_string(), accounts(2).into());
+}
+
+#[test]
+fn valid_prefixes_are_accepted() {
+    setup();
+    for prefix in ["show", "show-2022", "a_b-c", "x"] {
+        Contract::assert_valid_prefix(prefix);
+    }
+    Contract::assert_valid_prefix(&"a".repeat(58));
+}
+
+#[test]
+fn invalid_prefixes_are_rejected() {
+    setup();
+    for prefix in ["", "Show", "my.show", "-show", "show_", "a--b", "a-_b", "sh ow"] {
+        let result = std::panic::catch_unwind(|| Contract::assert_valid_prefix(prefix));
+        assert!(result.is_err(), "prefix {:?} was accepted", prefix);
+    }
+}
+
+#[test]
+#[should_panic(expected = "Prefix must be 1 to 58 characters long")]
+fn prefixes_keep_the_account_id_short() {
+    let mut contract = setup();
+    create(&mut contract, accounts(2), &"a".repeat(59));
+}
+
+#[test]
+#[should_panic(expected = "Prefix my.show is not a valid account id label")]
+fn invalid_prefixes_are_rejected_before_deploying() {
+    let mut contract = setup();
+    create(&mut contract, accounts(2), "my.show");
+}
+
+#[test]
+#[should_panic(expected = "Account show.alice is already taken")]
+fn a_prefix_is_created_once() {
+    let mut contract = setup();
+    create_contract(&mut contract, accounts(2), "show");
+    create(&mut contract, accounts(3), "show");
+}
+
+#[test]
+#[should_panic(expected = "Account show.alice is already taken")]
+fn a_prefix_being_created_can_not_be_requested_again() {
+    let mut contract = setup();
+    create(&mut contract, accounts(2), "show");
+    create(&mut contract, accounts(2), "show");
+}
+
+#[test]
+fn migrate_converts_the_first_layout() {
+    testing_env!(context(accounts(0)).build());
+    let mut by_owner: UnorderedMap<AccountId, Vec<AccountId>> =
+        UnorderedMap::new(b"ticket_contract_by_owner".to_vec());
+    by_owner.insert(&accounts(2).into(), &vec!["show.alice".to_string()]);
+    env::state_write(&ContractV0 {
+        owner_id: accounts(1).into(),
+        ticket_contracts_by_owner: by_owner,
+        ticket_contracts: vec!["show.alice".to_string()],
+    });
+
+    let mut contract = Contract::migrate();
+    assert_eq!(contract.owner_id, AccountId::from(accounts(1)));
+    assert_eq!(contract.get_contracts_by_owner(accounts(2).into()), vec!["show.alice"]);
+    assert_eq!(contract.get_all_contracts(0, 10), vec!["show.alice"]);
+    assert!(contract.first_version_contracts.contains(&"show.alice".to_string()));
+    let deposit = contract.get_create_contract_deposit().0;
+    assert_eq!(deposit, DEFAULT_CREATE_CONTRACT_FEE + DEFAULT_INITIAL_BALANCE);
+    // the recorded contracts stay taken
+    testing_env!(context(accounts(3)).attached_deposit(deposit).build());
+    let taken = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        contract.create_new_ticket_contract("show".to_string(), metadata(), None)
+    }));
+    assert!(taken.is_err());
+}
@@ -2,5 +2,6 @@
 set -e
 cd "`dirname $0`"
 source flags.sh
+export GIT_HASH=`git rev-parse --short HEAD 2>/dev/null || true`
 cargo build --all --target wasm32-unknown-unknown --release
 cp target/wasm32-unknown-unknown/release/*.wasm ./res/contract.wasm
\ No newline at end of file
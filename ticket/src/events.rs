@@ -0,0 +1,142 @@
+//! NEP-297 events, logged as `EVENT_JSON:{"data": [...], "event": ..., "standard": ..., "version": ...}`,
+//! `serde_json` writes the keys in alphabetical order
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::serde_json::{json, Value};
+use near_sdk::{log, AccountId, Balance, Timestamp};
+
+pub enum TicketEvent {
+    /// NEP-171 mint of tokens to one owner
+    NftMint {
+        owner_id: AccountId,
+        token_ids: Vec<TokenId>,
+    },
+    /// NEP-171 burn of tokens of one owner
+    NftBurn {
+        owner_id: AccountId,
+        token_ids: Vec<TokenId>,
+    },
+    TicketCheck {
+        ticket_id: TokenId,
+        show_id: String,
+    },
+    TicketPurchased {
+        buyer: AccountId,
+        token_id: TokenId,
+        show_id: String,
+        ticket_type: String,
+        price: Balance,
+        issued_at: Timestamp,
+    },
+    SupplyIncreased {
+        show_id: String,
+        ticket_type: String,
+        additional: u32,
+        supply: u32,
+    },
+    /// A ticket of a sold out type may be available to the account
+    WaitlistNotified {
+        account_id: AccountId,
+        show_id: String,
+        ticket_type: String,
+    },
+    /// All tickets of the source show are rebound to the target show
+    ShowMerged {
+        source_id: String,
+        target_id: String,
+        tickets: u64,
+    },
+}
+
+impl TicketEvent {
+    pub fn emit(&self) {
+        log!(
+            "EVENT_JSON:{}",
+            json!({
+                "standard": self.standard(),
+                "version": "1.0.0",
+                "event": self.name(),
+                "data": [self.data()],
+            })
+        );
+    }
+
+    fn standard(&self) -> &'static str {
+        match self {
+            TicketEvent::NftMint { .. } | TicketEvent::NftBurn { .. } => "nep171",
+            _ => "ticket",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            TicketEvent::NftMint { .. } => "nft_mint",
+            TicketEvent::NftBurn { .. } => "nft_burn",
+            TicketEvent::TicketCheck { .. } => "ticket_check",
+            TicketEvent::TicketPurchased { .. } => "ticket_purchased",
+            TicketEvent::SupplyIncreased { .. } => "supply_increased",
+            TicketEvent::WaitlistNotified { .. } => "waitlist_notified",
+            TicketEvent::ShowMerged { .. } => "show_merged",
+        }
+    }
+
+    fn data(&self) -> Value {
+        match self {
+            TicketEvent::NftMint {
+                owner_id,
+                token_ids,
+            }
+            | TicketEvent::NftBurn {
+                owner_id,
+                token_ids,
+            } => json!({ "owner_id": owner_id, "token_ids": token_ids }),
+            TicketEvent::TicketCheck { ticket_id, show_id } => {
+                json!({ "ticket_id": ticket_id, "show_id": show_id })
+            }
+            // amounts and times as strings, they do not fit in a JSON number
+            TicketEvent::TicketPurchased {
+                buyer,
+                token_id,
+                show_id,
+                ticket_type,
+                price,
+                issued_at,
+            } => json!({
+                "buyer": buyer,
+                "token_id": token_id,
+                "show_id": show_id,
+                "ticket_type": ticket_type,
+                "price": price.to_string(),
+                "issued_at": issued_at.to_string(),
+            }),
+            TicketEvent::SupplyIncreased {
+                show_id,
+                ticket_type,
+                additional,
+                supply,
+            } => json!({
+                "show_id": show_id,
+                "ticket_type": ticket_type,
+                "additional": additional,
+                "supply": supply,
+            }),
+            TicketEvent::WaitlistNotified {
+                account_id,
+                show_id,
+                ticket_type,
+            } => json!({
+                "account_id": account_id,
+                "show_id": show_id,
+                "ticket_type": ticket_type,
+            }),
+            TicketEvent::ShowMerged {
+                source_id,
+                target_id,
+                tickets,
+            } => json!({
+                "source_id": source_id,
+                "target_id": target_id,
+                "tickets": tickets,
+            }),
+        }
+    }
+}
@@ -1,34 +1,81 @@
 /*!
-Non-Fungible Token implementation with JSON serialization.
+Ticket NFTs for shows, with JSON serialization.
 NOTES:
   - The maximum balance value is limited by U128 (2**128 - 1).
   - JSON calls should pass U128 as a base-10 string. E.g. "100".
   - The contract optimizes the inner trie structure by hashing account IDs. It will prevent some
     abuse of deep tries. Shouldn't be an issue, once NEAR clients implement full hashing of keys.
-  - The contract tracks the change in storage before and after the call. If the storage increases,
-    the contract requires the caller of the contract to attach enough deposit to the function call
-    to cover the storage cost.
-    This is done to prevent a denial of service attack on the contract by taking all available storage.
-    If the storage decreases, the contract will issue a refund for the cost of the released storage.
-    The unused tokens from the attached deposit are also refunded, so it's safe to
-    attach more deposit than required.
+  - A purchase attaches the ticket price, the mint fee and TICKET_STORAGE_DEPOSIT up front, see
+    `ticket_price_breakdown`. `nft_private_mint` records the storage the new ticket takes, and
+    `check_mint` keeps the price, the mint fee and that storage cost, at most
+    TICKET_STORAGE_DEPOSIT, and refunds the rest of the deposit. A failed mint is refunded in
+    full.
+  - Other calls that add storage, like `join_waitlist` or `convert_to_collectible`, charge it
+    from the attached deposit and refund the rest. Calls that release storage, like
+    `burn_ticket`, `refund_ticket` or `remove_show`, refund the released storage to the caller.
   - To prevent the deployed contract from being modified or deleted, it should not have any access
     keys on its account.
 */
-use near_contract_standards::non_fungible_token::metadata::TokenMetadata;
-use near_contract_standards::non_fungible_token::NonFungibleToken;
+use near_contract_standards::non_fungible_token::core::{
+    NonFungibleTokenCore, NonFungibleTokenResolver,
+};
+use near_contract_standards::non_fungible_token::metadata::{
+    NFTContractMetadata, NonFungibleTokenMetadataProvider, TokenMetadata,
+};
+use near_contract_standards::non_fungible_token::{refund_deposit, NonFungibleToken};
 use near_contract_standards::non_fungible_token::{Token, TokenId};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, UnorderedMap, UnorderedSet};
-use near_sdk::json_types::ValidAccountId;
+use near_sdk::collections::{LazyOption, LookupMap, UnorderedMap, UnorderedSet, Vector};
+use near_sdk::json_types::{Base64VecU8, ValidAccountId, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
     assert_one_yocto, env, ext_contract, log, near_bindgen, AccountId, Balance, BorshStorageKey,
     Gas, PanicOnDefault, Promise, PromiseOrValue, PromiseResult, Timestamp,
 };
+use std::collections::{HashMap, HashSet};
+
+use crate::events::TicketEvent;
+use crate::migrate::ContractV0;
+
+mod events;
+mod migrate;
+#[cfg(test)]
+mod tests;
 
-const MINT_FEE: Balance = 1_000_000_000_000_000_000_000_0;
-const PREPARE_GAS: Gas = 1_500_000_000_000_0;
+// The deposit of a purchase is the ticket's face value plus these two components.
+// The mint fee is attached to `nft_private_mint`, the NFT core pays the token storage from it
+// and the rest stays on the contract. TICKET_STORAGE_DEPOSIT is the most the storage of a
+// mint is charged, the part the mint did not use is refunded by `check_mint`.
+const DEFAULT_MINT_FEE: Balance = 10_000_000_000_000_000_000_000;
+const TICKET_STORAGE_DEPOSIT: Balance = 5_000_000_000_000_000_000_000;
+// the mint fee must at least cover the storage of a token, the NFT core rejects mints it
+// does not cover
+const MIN_MINT_FEE: Balance = TICKET_STORAGE_DEPOSIT;
+const DEFAULT_PREPARE_GAS: Gas = 15_000_000_000_000;
+const MIN_PREPARE_GAS: Gas = 5_000_000_000_000;
+// most gas a transaction can attach
+const MAX_PREPAID_GAS: Gas = 300_000_000_000_000;
+const CHECK_MINT_GAS: Gas = 25_000_000_000_000;
+// Gas buy_ticket itself needs after the checks to schedule the promise chain
+const SCHEDULE_GAS: Gas = 5_000_000_000_000;
+const BLOCK_MINT_CALLBACK_GAS: Gas = 10_000_000_000_000;
+const ORACLE_GAS: Gas = 10_000_000_000_000;
+const FT_TRANSFER_GAS: Gas = 10_000_000_000_000;
+const MIGRATE_GAS: Gas = 50_000_000_000_000;
+const USD_RATE_TTL: Timestamp = 300_000_000_000;
+const MAX_BATCH_SIZE: usize = 100;
+const DEFAULT_TICKET_MEDIA: &str = "https://res.cloudinary.com/dcrbaasbt/image/upload/v1639640365/265266702_588262069101334_1825137514299467956_n_tiyp60.png";
+const BPS_DENOMINATOR: u16 = 10_000;
+const MAX_TITLE_LENGTH: usize = 256;
+const MAX_DESCRIPTION_LENGTH: usize = 4096;
+const MAX_CATEGORIES: usize = 10;
+const MAX_CATEGORY_LENGTH: usize = 64;
+const MAX_ID_LENGTH: usize = 64;
+const MAX_TICKET_PRICE: Balance = 1_000_000_000 * ONE_NEAR;
+const ONE_NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+const MAX_BLOCK_MINTS_PER_CALL: u32 = 10;
+const MAX_TICKETS_PER_PURCHASE: u32 = 5; // every ticket needs its own mint and check_mint gas
+const MAX_ROYALTY_ACCOUNTS: usize = 10;
 near_sdk::setup_alloc!();
 
 #[near_bindgen]
@@ -39,6 +86,39 @@ pub struct Contract {
     metadata: LazyOption<TicketContractMetadata>,
     shows: UnorderedMap<String, ShowMetadata>,
     tickets: UnorderedMap<TokenId, TicketMetadata>,
+    show_merges: UnorderedMap<String, ShowMerge>,
+    checkin_refund_bps: u16,
+    require_show_details: bool,
+    price_oracle: Option<PriceOracle>,
+    usd_rate: Option<UsdRate>,
+    next_serials: LookupMap<String, u32>,
+    pending_refunds: UnorderedMap<AccountId, Balance>,
+    total_pending_refunds: Balance,
+    max_shows: Option<u64>,
+    reserved_tickets: LookupMap<String, u32>,
+    block_allocations: Vector<BlockAllocation>,
+    factory_id: Option<AccountId>,
+    deployed_at: Timestamp,
+    code_version: Option<String>, // set by the factory, see `contract_version`
+    tokens_per_show: LookupMap<String, UnorderedSet<TokenId>>,
+    cancel_refund_progress: LookupMap<String, u64>,
+    purchases_per_account: UnorderedMap<(String, AccountId), u32>,
+    allowlists: LookupMap<String, UnorderedSet<AccountId>>,
+    pending_owner: Option<AccountId>,
+    scanners: LookupMap<String, UnorderedSet<AccountId>>,
+    revenue: UnorderedMap<String, Balance>,
+    mint_fee: Balance,
+    prepare_gas: Gas,
+    ft_revenue: UnorderedMap<String, Balance>,
+    paused: bool,
+    claimed_seats: UnorderedSet<(String, String)>,
+    resale_listings: UnorderedMap<TokenId, ResaleListing>,
+    gross_revenue: UnorderedMap<String, Balance>,
+    waitlists: LookupMap<String, Waitlist>,
+    waitlisted: UnorderedSet<(String, AccountId)>,
+    tickets_checked: u64,
+    pending_mints: LookupMap<String, u32>,
+    ticket_migration: Option<u64>, // index of the next ticket `migrate_tickets` converts
 }
 
 #[derive(BorshSerialize, BorshStorageKey)]
@@ -50,12 +130,40 @@ enum StorageKey {
     Approval,
     ShowMetadata,
     TicketMetadata,
+    ShowMerge,
+    NextSerials,
+    PendingRefunds,
+    ReservedTickets,
+    BlockAllocations,
+    TokensPerShow,
+    ShowTokens { show_hash: Vec<u8> },
+    CancelRefundProgress,
+    PurchasesPerAccount,
+    Allowlists,
+    ShowAllowlist { show_hash: Vec<u8> },
+    Scanners,
+    ShowScanners { show_hash: Vec<u8> },
+    Revenue,
+    FtRevenue,
+    ClaimedSeats,
+    ResaleListings,
+    GrossRevenue,
+    Waitlists,
+    TypeWaitlist { type_hash: Vec<u8> },
+    Waitlisted,
+    PendingMints,
 }
 
 #[near_bindgen]
 impl Contract {
     #[init]
-    pub fn new(owner_id: AccountId, metadata: TicketContractMetadata) -> Self {
+    pub fn new(
+        owner_id: AccountId,
+        metadata: TicketContractMetadata,
+        factory_id: Option<AccountId>,
+        deployed_at: Option<Timestamp>,
+        code_version: Option<String>,
+    ) -> Self {
         assert!(!env::state_exists(), "Already initialized");
         Self {
             owner_id,
@@ -69,63 +177,204 @@ impl Contract {
             metadata: LazyOption::new(StorageKey::Metadata, Some(&metadata)),
             shows: UnorderedMap::new(StorageKey::ShowMetadata),
             tickets: UnorderedMap::new(StorageKey::TicketMetadata),
+            show_merges: UnorderedMap::new(StorageKey::ShowMerge),
+            checkin_refund_bps: BPS_DENOMINATOR,
+            require_show_details: false,
+            price_oracle: None,
+            usd_rate: None,
+            next_serials: LookupMap::new(StorageKey::NextSerials),
+            pending_refunds: UnorderedMap::new(StorageKey::PendingRefunds),
+            total_pending_refunds: 0,
+            max_shows: None,
+            reserved_tickets: LookupMap::new(StorageKey::ReservedTickets),
+            block_allocations: Vector::new(StorageKey::BlockAllocations),
+            factory_id,
+            deployed_at: deployed_at.unwrap_or_else(env::block_timestamp),
+            code_version,
+            tokens_per_show: LookupMap::new(StorageKey::TokensPerShow),
+            cancel_refund_progress: LookupMap::new(StorageKey::CancelRefundProgress),
+            purchases_per_account: UnorderedMap::new(StorageKey::PurchasesPerAccount),
+            allowlists: LookupMap::new(StorageKey::Allowlists),
+            pending_owner: None,
+            scanners: LookupMap::new(StorageKey::Scanners),
+            revenue: UnorderedMap::new(StorageKey::Revenue),
+            mint_fee: DEFAULT_MINT_FEE,
+            prepare_gas: DEFAULT_PREPARE_GAS,
+            ft_revenue: UnorderedMap::new(StorageKey::FtRevenue),
+            paused: false,
+            claimed_seats: UnorderedSet::new(StorageKey::ClaimedSeats),
+            resale_listings: UnorderedMap::new(StorageKey::ResaleListings),
+            gross_revenue: UnorderedMap::new(StorageKey::GrossRevenue),
+            waitlists: LookupMap::new(StorageKey::Waitlists),
+            waitlisted: UnorderedSet::new(StorageKey::Waitlisted),
+            tickets_checked: 0,
+            pending_mints: LookupMap::new(StorageKey::PendingMints),
+            ticket_migration: None,
         }
     }
 
     pub fn transfer_ownership(&mut self, new_owner: ValidAccountId) {
+        self.assert_owner();
+        self.pending_owner = Some(new_owner.into());
+    }
+
+    /// Complete `transfer_ownership`, called by the proposed owner
+    pub fn accept_ownership(&mut self) {
         assert!(
-            env::predecessor_account_id() == self.owner_id,
-            "{}",
-            format!(
-                "Caller {} is not owner: {}",
-                env::predecessor_account_id(),
-                self.owner_id
-            )
+            self.pending_owner == Some(env::predecessor_account_id()),
+            "Caller {} is not the pending owner",
+            env::predecessor_account_id()
         );
-        self.owner_id = new_owner.into();
+        self.owner_id = self.pending_owner.take().unwrap();
+        log!("Ownership is transferred to {}", self.owner_id);
     }
 
-    pub fn renounce_ownership(&mut self) {
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner.clone()
+    }
+
+    /// Stop ticket sales and check-ins, e.g. while an incident is investigated
+    pub fn set_paused(&mut self, paused: bool) {
+        self.assert_owner();
         assert!(
-            env::predecessor_account_id() == self.owner_id,
-            "{}",
-            format!(
-                "Caller {} is not owner: {}",
-                env::predecessor_account_id(),
-                self.owner_id
-            )
+            paused || self.ticket_migration.is_none(),
+            "Tickets are being migrated, migrate_tickets unpauses the contract when done"
         );
+        self.paused = paused;
+        log!("Contract paused: {}", paused);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// True after `renounce_ownership`, no owner action can be taken anymore
+    pub fn is_ownerless(&self) -> bool {
+        self.owner_id.is_empty()
+    }
+
+    pub fn renounce_ownership(&mut self) {
+        self.assert_owner();
         self.owner_id = String::new();
+        self.pending_owner = None;
     }
     // Add ticket info
     pub fn add_ticket_info(&mut self, show_id: String,  info: TicketInfo){
-        assert!(!self.shows.get(&show_id).is_none(), "This show not exist");
-        assert!(
-            env::predecessor_account_id() == self.owner_id,
-            "Caller {} is not owner: {}",
-            env::predecessor_account_id(),
-            self.owner_id
-        );
+        assert!(self.shows.get(&show_id).is_some(), "This show not exist");
+        self.assert_owner();
         let mut show = self.shows.get(&show_id).unwrap();
-        assert!(show.ticket_infos.get(&info.ticket_type).is_none(), "This ticket info already exist");
+        assert!(!show.ticket_infos.contains_key(&info.ticket_type), "This ticket info already exist");
+        // the same checks as the types of a new show
+        Self::assert_valid(
+            Self::ticket_info_errors(&info.ticket_type, info.supply, info.price.0)
+                .into_iter()
+                .map(|(field, message)| ValidationError::new(field, message))
+                .collect(),
+        );
+        Self::assert_pool_exists(&show, &info);
+        if let Some(message) = Self::window_error(info.selling_start_time, info.selling_end_time) {
+            env::panic(format!("selling_end_time: {}", message).as_bytes());
+        }
+        // nothing is sold of a new type
+        let mut info = info;
+        info.sold = 0;
         show.ticket_infos.insert(info.ticket_type.clone(), info);
         self.shows.insert(&show_id, &show);
     }   
     // Edit ticket info
     pub fn edit_ticket_info(&mut self, show_id: String,  info: TicketInfo){
-        assert!(!self.shows.get(&show_id).is_none(), "This show not exist");
+        assert!(self.shows.get(&show_id).is_some(), "This show not exist");
+        self.assert_owner();
+        let mut show = self.shows.get(&show_id).unwrap();
+        assert!(show.ticket_infos.contains_key(&info.ticket_type), "This ticket is not exist");
+        Self::assert_pool_exists(&show, &info);
+        if let Some(message) = Self::window_error(info.selling_start_time, info.selling_end_time) {
+            env::panic(format!("selling_end_time: {}", message).as_bytes());
+        }
+        // the sold counter is kept by the contract, the supply can not go below it
+        let mut info = info;
+        info.sold = show.ticket_infos[&info.ticket_type].sold;
+        let taken = self.taken_of(&show_id, &info);
         assert!(
-            env::predecessor_account_id() == self.owner_id,
-            "Caller {} is not owner: {}",
-            env::predecessor_account_id(),
-            self.owner_id
+            info.supply >= taken,
+            "Supply can not be lower than the {} tickets sold, reserved or being minted",
+            taken
         );
-        let mut show = self.shows.get(&show_id).unwrap();
-        assert!(!show.ticket_infos.get(&info.ticket_type).is_none(), "This ticket is not exist");
+        let added = info.supply.saturating_sub(show.ticket_infos[&info.ticket_type].supply);
+        let ticket_type = info.ticket_type.clone();
         show.ticket_infos.insert(info.ticket_type.clone(), info);
         self.shows.insert(&show_id, &show);
+        self.notify_waitlist(&show_id, &ticket_type, added);
     }   
+    /// Set the price of `ticket_type` in each of `show_ids`, at most `MAX_BATCH_SIZE`.
+    /// Cancelled shows, shows without the type and shows pricing it in USD are skipped,
+    /// returns the shows that were updated.
+    pub fn set_type_price_across_shows(
+        &mut self,
+        show_ids: Vec<String>,
+        ticket_type: String,
+        new_price: U128,
+    ) -> Vec<String> {
+        self.assert_owner();
+        assert!(
+            show_ids.len() <= MAX_BATCH_SIZE,
+            "Can update at most {} shows at once",
+            MAX_BATCH_SIZE
+        );
+        assert!(
+            new_price.0 <= MAX_TICKET_PRICE,
+            "Ticket price must be at most {} yoctoNEAR",
+            MAX_TICKET_PRICE
+        );
+        let mut updated = Vec::new();
+        for show_id in show_ids {
+            let mut show = match self.shows.get(&show_id) {
+                Some(show) if !show.is_cancelled => show,
+                _ => continue,
+            };
+            // the NEAR price of USD priced types is derived from `usd_cents`
+            if let Some(info) = show
+                .ticket_infos
+                .get_mut(&ticket_type)
+                .filter(|info| info.usd_cents.is_none())
+            {
+                info.price = new_price;
+                self.shows.insert(&show_id, &show);
+                updated.push(show_id);
+            }
+        }
+        updated
+    }
+
+    /// Release `additional` more tickets of a type, the sold counter is kept
+    pub fn increase_supply(&mut self, show_id: String, ticket_type: String, additional: u32) {
+        self.assert_owner();
+        assert!(additional > 0, "additional must be at least 1");
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        let info = show
+            .ticket_infos
+            .get_mut(&ticket_type)
+            .unwrap_or_else(|| env::panic(b"This ticket is not exist"));
+        info.supply = info
+            .supply
+            .checked_add(additional)
+            .unwrap_or_else(|| env::panic(b"Supply overflow"));
+        let supply = info.supply;
+        self.shows.insert(&show_id, &show);
+        TicketEvent::SupplyIncreased {
+            show_id: show_id.clone(),
+            ticket_type: ticket_type.clone(),
+            additional,
+            supply,
+        }
+        .emit();
+        self.notify_waitlist(&show_id, &ticket_type, additional);
+    }
     /// Create new show
+    #[allow(clippy::too_many_arguments)]
     pub fn create_new_show(
         &mut self,
         show_id: String, // required,
@@ -135,267 +384,3918 @@ impl Contract {
         show_banner: Option<String>,
         ticket_types: Vec<String>,     // required, type ticket => amount
         tickets_supply: Vec<u32>,      // required
-        ticket_prices: Vec<U128>,       // required, type ticket =>
+        ticket_prices: Vec<U128>,      // required, yoctoNEAR
         selling_start_time: Timestamp, // required
         selling_end_time: Timestamp,
+        categories: Option<Vec<String>>,
+        capacity_pools: Option<HashMap<String, u32>>, // pool => capacity shared by its types
+        ticket_pools: Option<Vec<Option<String>>>,    // pool of each ticket type
+        ticket_selling_windows: Option<Vec<(Option<Timestamp>, Option<Timestamp>)>>, // [start, end] of each ticket type, None => show window
+        royalty: Option<HashMap<AccountId, u16>>, // account => basis points of resales
+        splits: Option<Vec<(AccountId, u16)>>, // account => basis points of the revenue
+        metadata_templates: Option<Vec<Option<MetadataTemplate>>>, // token metadata of each ticket type
+        event_start_time: Option<Timestamp>, // when the event itself takes place
+        event_end_time: Option<Timestamp>,   // tickets expire then, None => at the end of sales
+        ticket_usd_prices: Option<Vec<Option<u64>>>, // US cents of each ticket type, None => ticket_prices
+    ) {
+        self.assert_owner();
+        self.create_show(NewShowInput {
+            show_id,
+            show_title,
+            show_description,
+            show_time,
+            show_banner,
+            ticket_types,
+            tickets_supply,
+            ticket_prices,
+            selling_start_time,
+            selling_end_time,
+            categories,
+            capacity_pools,
+            ticket_pools,
+            ticket_selling_windows,
+            royalty,
+            splits,
+            metadata_templates,
+            event_start_time,
+            event_end_time,
+            ticket_usd_prices,
+        });
+    }
+
+    /// Deprecated: `create_new_show` with prices in NEAR instead of yoctoNEAR
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_new_show_near_prices(
+        &mut self,
+        show_id: String,
+        show_title: Option<String>,
+        show_description: Option<String>,
+        show_time: Timestamp,
+        show_banner: Option<String>,
+        ticket_types: Vec<String>,
+        tickets_supply: Vec<u32>,
+        ticket_prices: Vec<f64>, // NEAR
+        selling_start_time: Timestamp,
+        selling_end_time: Timestamp,
+        categories: Option<Vec<String>>,
+        capacity_pools: Option<HashMap<String, u32>>,
+        ticket_pools: Option<Vec<Option<String>>>,
+        ticket_selling_windows: Option<Vec<(Option<Timestamp>, Option<Timestamp>)>>,
+        royalty: Option<HashMap<AccountId, u16>>,
+        splits: Option<Vec<(AccountId, u16)>>,
+        metadata_templates: Option<Vec<Option<MetadataTemplate>>>,
+        event_start_time: Option<Timestamp>,
+        event_end_time: Option<Timestamp>,
     ) {
-        assert!(self.shows.get(&show_id).is_none(), "This show exist");
+        let mut errors = Vec::new();
+        let ticket_prices = ticket_prices
+            .iter()
+            .enumerate()
+            .map(|(i, near)| {
+                Self::near_to_yocto(*near).unwrap_or_else(|| {
+                    errors.push(ValidationError::new(
+                        &format!("ticket_prices[{}]", i),
+                        "Ticket price must be a non negative amount of NEAR".to_string(),
+                    ));
+                    0
+                })
+            })
+            .map(U128)
+            .collect();
+        Self::assert_valid(errors);
+        self.create_new_show(
+            show_id,
+            show_title,
+            show_description,
+            show_time,
+            show_banner,
+            ticket_types,
+            tickets_supply,
+            ticket_prices,
+            selling_start_time,
+            selling_end_time,
+            categories,
+            capacity_pools,
+            ticket_pools,
+            ticket_selling_windows,
+            royalty,
+            splits,
+            metadata_templates,
+            event_start_time,
+            event_end_time,
+            None,
+        );
+    }
+
+    /// Every problem `create_new_show` would reject `input` for, without creating the show
+    pub fn validate_show_config(&self, input: NewShowInput) -> Vec<ValidationError> {
+        self.show_config_errors(&input)
+    }
+
+    pub fn set_show_categories(&mut self, show_id: String, categories: Vec<String>) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        Self::assert_valid(Self::category_errors(&categories));
+        show.categories = categories;
+        self.shows.insert(&show_id, &show);
+    }
+    pub fn set_show_royalty(&mut self, show_id: String, royalty: HashMap<AccountId, u16>) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        Self::assert_valid(Self::royalty_errors(&royalty));
+        show.royalty = royalty;
+        self.shows.insert(&show_id, &show);
+    }
+
+    /// Share the revenue of a show, the basis points must add up to 10000.
+    /// An empty list sends all of it to the owner.
+    pub fn set_show_splits(&mut self, show_id: String, splits: Vec<(AccountId, u16)>) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        Self::assert_valid(Self::split_errors(&splits));
+        show.splits = splits;
+        self.shows.insert(&show_id, &show);
+    }
+
+    /// NEP-199 payout of a resale of `token_id` for `balance`, split by the royalty of
+    /// its show with the rest going to the holder
+    pub fn nft_payout(&self, token_id: TokenId, balance: U128, max_len_payout: u32) -> Payout {
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .unwrap_or_else(|| env::panic(b"Token not found"));
+        let royalty = self
+            .tickets
+            .get(&token_id)
+            .and_then(|ticket| self.shows.get(&ticket.show_id))
+            .map(|show| show.royalty)
+            .unwrap_or_default();
         assert!(
-            env::predecessor_account_id() == self.owner_id,
-            "{}",
-            format!(
-                "Caller {} is not owner: {}",
-                env::predecessor_account_id(),
-                self.owner_id
-            )
+            royalty.len() < max_len_payout as usize,
+            "Payout has more than {} receivers",
+            max_len_payout
         );
-        let mut ticket_infos = HashMap::new();
-        for i in 0..ticket_types.len() {
-            let price: Balance = (ticket_prices[i] * 1_000_000_000_000_000_000_000_000u128 as f64)
-                .round() as Balance
-                + MINT_FEE;
-            let ticket_info = TicketInfo {
-                supply: tickets_supply[i],            // required
-                ticket_type: ticket_types[i].clone(), // required,
-                price: price,
-                sold: 0u32,
-                selling_start_time: Some(0u64),
-                selling_end_time: Some(0u64)
-            };
-            ticket_infos.insert(ticket_types[i].clone(), ticket_info);
+        let mut payout = HashMap::new();
+        let mut paid: Balance = 0;
+        for (account_id, bps) in royalty {
+            if account_id == owner_id {
+                continue;
+            }
+            let amount = balance.0 * bps as Balance / BPS_DENOMINATOR as Balance;
+            paid += amount;
+            payout.insert(account_id, U128(amount));
         }
+        payout.insert(owner_id, U128(balance.0 - paid));
+        Payout { payout }
+    }
 
-        self.shows.insert(
-            &show_id.clone(),
-            &ShowMetadata {
-                show_id,
-                show_title,
-                show_description,
-                ticket_infos,
-                show_time,
-                show_banner,
-                selling_start_time,
-                selling_end_time,
-            },
+    /// NEP-199 transfer returning the payout of the sale
+    #[payable]
+    pub fn nft_transfer_payout(
+        &mut self,
+        receiver_id: ValidAccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        balance: U128,
+        max_len_payout: u32,
+    ) -> Payout {
+        assert_one_yocto();
+        self.assert_transferable(&token_id);
+        let payout = self.nft_payout(token_id.clone(), balance, max_len_payout);
+        self.tokens.internal_transfer(
+            &env::predecessor_account_id(),
+            receiver_id.as_ref(),
+            &token_id,
+            approval_id,
+            memo,
         );
+        payout
     }
+
+    /// Media of the tickets minted from now on, `base_uri` takes precedence over `media`
+    pub fn set_show_media(&mut self, show_id: String, media: Option<String>, base_uri: Option<String>) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        show.media = media;
+        show.base_uri = base_uri;
+        self.shows.insert(&show_id, &show);
+    }
+
+    /// Cap resale prices of a show at `max_resale_multiplier` basis points of the face
+    /// value, ex. 15000 => 1.5x. None disables resale.
+    pub fn set_max_resale_multiplier(&mut self, show_id: String, max_resale_multiplier: Option<u32>) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        show.max_resale_multiplier = max_resale_multiplier;
+        self.shows.insert(&show_id, &show);
+    }
+
+    /// Cap resale prices of a ticket type at `max_resale_markup_bps` basis points above the
+    /// face value, ex. 0 => at cost, 5000 => 1.5x. None falls back to the show's
+    /// `max_resale_multiplier`.
+    pub fn set_max_resale_markup(
+        &mut self,
+        show_id: String,
+        ticket_type: String,
+        max_resale_markup_bps: Option<u16>,
+    ) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        let info = show
+            .ticket_infos
+            .get_mut(&ticket_type)
+            .unwrap_or_else(|| env::panic(b"This ticket is not exist"));
+        info.max_resale_markup_bps = max_resale_markup_bps;
+        self.shows.insert(&show_id, &show);
+    }
+
+    /// Highest yoctoNEAR price a ticket of the type can be listed for, None when it can
+    /// not be resold. USD priced types need a cached NEAR/USD rate.
+    pub fn max_resale_price(&self, show_id: String, ticket_type: String) -> Option<U128> {
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        self.resale_cap_of(&show, &ticket_type).map(U128)
+    }
+
+    /// List an unused ticket for resale at `price` yoctoNEAR, replacing an earlier
+    /// listing of it. USD priced tickets need a cached NEAR/USD rate for the cap.
     #[payable]
-    pub fn buy_ticket(&mut self, show_id: String, ticket_type: String) -> Promise {
-        let show = self.shows.get(&show_id).unwrap();
-        assert!(
-            env::block_timestamp() > show.selling_start_time,
-            "This show has not started selling tickets yet {}",
-            show.selling_start_time
-            
-        );
-        assert!(
-            env::block_timestamp() < show.selling_end_time,
-            "This show has ended ticket sales {}", show.selling_end_time
-        );
-        assert!(
-            show.ticket_infos.get(&ticket_type).unwrap().sold
-                < show.ticket_infos.get(&ticket_type).unwrap().supply,
-            "All tickets are sold out"
-        );
-        assert!(
-            env::attached_deposit() >= show.ticket_infos.get(&ticket_type).unwrap().price,
-            "Please deposit exactly price of ticket {}. You deposit {}",
-            show.ticket_infos.get(&ticket_type).unwrap().price,
-            env::attached_deposit()
-            
-        );
-        let ticket_id = format!(
-            "{}.{}.{}",
-            show_id,
-            ticket_type,
-            show.ticket_infos.get(&ticket_type).unwrap().sold
+    pub fn list_for_resale(&mut self, ticket_id: TokenId, price: U128) {
+        assert_one_yocto();
+        self.assert_not_paused();
+        let seller = env::predecessor_account_id();
+        assert_eq!(
+            self.tokens.owner_by_id.get(&ticket_id),
+            Some(seller.clone()),
+            "Only the owner of ticket {} can list it",
+            ticket_id
         );
-        log!(
-            "{}",
-            format!(
-                "Buy new ticket: show id: {}, ticket type: {}, ticket id: {}, price: {} YoctoNear",
-                show_id,
-                ticket_type,
-                ticket_id,
-                show.ticket_infos.get(&ticket_type).unwrap().price
+        self.assert_transferable(&ticket_id);
+        let ticket = self.tickets.get(&ticket_id).unwrap();
+        let show = self.shows.get(&ticket.show_id).unwrap();
+        Self::assert_resellable(&ticket, &show);
+        let max_price = self.resale_cap_of(&show, &ticket.ticket_type).unwrap_or_else(|| {
+            env::panic(
+                format!("Tickets {} of show {} can not be resold", ticket.ticket_type, show.show_id)
+                    .as_bytes(),
             )
+        });
+        assert!(
+            price.0 > 0 && price.0 <= max_price,
+            "Resale price must be 1 to {} YoctoNear",
+            max_price
         );
-        ex_self::nft_private_mint(
-            ticket_id,
-            ValidAccountId::try_from(env::predecessor_account_id()).unwrap(),
-            &env::current_account_id(),
-            MINT_FEE,
-            PREPARE_GAS,
-        )
-        .then(ex_self::check_mint(
-            env::predecessor_account_id(),
-            show.ticket_infos.get(&ticket_type).unwrap().price,
-            &env::current_account_id(),
-            0,
-            5_000_000_000_000_0,
-        ))
+        self.resale_listings.insert(&ticket_id, &ResaleListing { seller, price });
     }
 
     #[payable]
-    pub fn check_ticket(&mut self, ticket_id: String) {
+    pub fn cancel_resale(&mut self, ticket_id: TokenId) {
         assert_one_yocto();
-        assert!(
-            self.tokens.owner_by_id.get(&ticket_id) == Some(env::predecessor_account_id()),
-            "You do not own the ticket {}",
-            self.tokens.owner_by_id.get(&ticket_id).unwrap()
-            
-        );
-        let mut ticket = self
-            .tickets
+        let listing = self
+            .resale_listings
             .get(&ticket_id)
-            .unwrap_or_else(|| env::panic(b"ticket id does not exist!"));
-        ticket.is_used = true;
-        self.tickets.insert(&ticket_id, &ticket);
-        log!("{}", format!("Ticket {} is checked", ticket_id));
-    }
-    #[payable]
-    #[private]
-    pub fn nft_private_mint(&mut self, token_id: TokenId, receiver_id: ValidAccountId) -> Token {
-        let token_id_split: Vec<&str> = token_id.split(".").collect();
-        let show_id = token_id_split[0].to_string();
-        let ticket_type = token_id_split[1].to_string();
-        let mut show = self.shows.get(&show_id).unwrap();
-        let mut ticket_info = show.ticket_infos.get(&ticket_type).unwrap().clone();
-        ticket_info.sold += 1;
-        show.ticket_infos.insert(ticket_type.clone(), ticket_info);
-        self.shows.insert(&show_id, &show);
-        self.tickets.insert(
-            &token_id,
-            &TicketMetadata {
-                ticket_id: token_id.clone(),
-                show_id,
-                ticket_type,
-                is_used: false,
-                issued_at: env::block_timestamp(),
-                show: None,
-            },
+            .unwrap_or_else(|| env::panic(b"Ticket is not listed"));
+        assert_eq!(
+            listing.seller,
+            env::predecessor_account_id(),
+            "Only the seller can cancel the listing"
         );
-        self.tokens.mint(
-            token_id,
-            receiver_id,
-            Some(TokenMetadata {
-                title: Some("B-Event".to_string()), // ex. "Arch Nemesis: Mail Carrier" or "Parcel #5055"
-                description: Some("B-Event ticket".to_string()), // free-form description
-                media: Some("https://res.cloudinary.com/dcrbaasbt/image/upload/v1639640365/265266702_588262069101334_1825137514299467956_n_tiyp60.png".to_string()), // URL to associated media, preferably to decentralized, content-addressed storage
-                media_hash: None, // Base64-encoded sha256 hash of content referenced by the `media` field. Required if `media` is included.
-                copies: Some(1), // number of copies of this set of metadata in existence when token was minted.
-                issued_at: Some(env::block_timestamp().to_string()), // ISO 8601 datetime when token was issued or minted
-                expires_at: None,     // ISO 8601 datetime when token expires
-                starts_at: None,      // ISO 8601 datetime when token starts being valid
-                updated_at: None,     // ISO 8601 datetime when token was last updated
-                extra: None, // anything extra the NFT wants to store on-chain. Can be stringified JSON.
-                reference: None, // URL to an off-chain JSON file with more info.
-                reference_hash: None, // Base64-encoded sha256 hash of JSON from reference field. Required if `reference` is included.
-            }),
-        )
+        self.resale_listings.remove(&ticket_id);
     }
 
-    pub fn check_mint(&self, buyer: AccountId, price: Balance) {
-        let mut result: bool = true;
-        for i in 0..env::promise_results_count() {
-            if env::promise_result(i) == PromiseResult::Failed {
-                result = false;
-                break;
+    /// Buy a listed ticket, the price is split by the royalty of the show like
+    /// `nft_payout` and the deposit above it is refunded
+    #[payable]
+    pub fn buy_resale(&mut self, ticket_id: TokenId) -> Payout {
+        self.assert_not_paused();
+        let listing = self
+            .resale_listings
+            .get(&ticket_id)
+            .unwrap_or_else(|| env::panic(b"Ticket is not listed"));
+        assert_eq!(
+            self.tokens.owner_by_id.get(&ticket_id),
+            Some(listing.seller.clone()),
+            "Listing of ticket {} is no longer valid",
+            ticket_id
+        );
+        let buyer = env::predecessor_account_id();
+        assert_ne!(buyer, listing.seller, "Can not buy your own ticket");
+        // the ticket may have been used or its show cancelled since it was listed
+        let ticket = self.tickets.get(&ticket_id).unwrap();
+        Self::assert_resellable(&ticket, &self.shows.get(&ticket.show_id).unwrap());
+        assert!(
+            env::attached_deposit() >= listing.price.0,
+            "Please deposit at least {}. You deposit {}",
+            listing.price.0,
+            env::attached_deposit()
+        );
+        self.assert_transferable(&ticket_id);
+        let payout = self.nft_payout(
+            ticket_id.clone(),
+            listing.price,
+            MAX_ROYALTY_ACCOUNTS as u32 + 1,
+        );
+        self.resale_listings.remove(&ticket_id);
+        self.tokens.internal_transfer(&listing.seller, &buyer, &ticket_id, None, None);
+        for (account_id, amount) in payout.payout.iter() {
+            if amount.0 > 0 {
+                Promise::new(account_id.clone()).transfer(amount.0);
             }
         }
-        if result == false {
-            log!("Fail to create new ticket contract");
-            Promise::new(buyer).transfer(price);
+        if env::attached_deposit() > listing.price.0 {
+            Promise::new(buyer).transfer(env::attached_deposit() - listing.price.0);
         }
+        payout
     }
 
-    pub fn get_active_shows(&self) -> Vec<ShowMetadata> {
-        self.shows
-            .values()
-            .filter_map(|show| {
-                if show.selling_start_time < env::block_timestamp()
-                    && show.selling_end_time > env::block_timestamp()
-                {
-                    Some(show)
-                } else {
-                    None
-                }
-            })
-            .collect()
+    pub fn get_resale_listing(&self, ticket_id: TokenId) -> Option<ResaleListing> {
+        self.resale_listings.get(&ticket_id)
     }
 
-    pub fn get_all_shows(&self) -> Vec<ShowMetadata> {
-        self.shows.values().collect()
+    /// Make a show visible in discovery views and open it for sale
+    pub fn publish_show(&mut self, show_id: String) {
+        self.set_show_published(show_id, true);
     }
 
-    pub fn show_metadata(&self, show_id: String) -> ShowMetadata {
-        self.shows.get(&show_id).unwrap()
+    pub fn unpublish_show(&mut self, show_id: String) {
+        self.set_show_published(show_id, false);
     }
 
-    pub fn ticket_metadata(&self, token_id: TokenId) -> TicketMetadata {
-        let mut _ticket = self.tickets.get(&token_id).unwrap();
-        _ticket.show = self.shows.get(&_ticket.show_id);
-        _ticket
+    /// Limit the number of shows of this contract, `None` for no limit
+    pub fn set_max_shows(&mut self, max_shows: Option<u64>) {
+        self.assert_owner();
+        self.max_shows = max_shows;
     }
 
-    pub fn get_tickets_by_owner(&self, owner: AccountId) -> Vec<TicketMetadata> {
-        let token_ids = self
-            .tokens
-            .tokens_per_owner
-            .as_ref()
-            .unwrap()
-            .get(&owner)
-            .unwrap_or_else(|| UnorderedSet::new(b"".to_vec()));
-        token_ids
-            .iter()
-            .map(|token_id: TokenId| self.ticket_metadata(token_id))
-            .collect()
+    pub fn get_max_shows(&self) -> Option<u64> {
+        self.max_shows
     }
-}
-
-near_contract_standards::impl_non_fungible_token_core!(Contract, tokens);
-near_contract_standards::impl_non_fungible_token_approval!(Contract, tokens);
-near_contract_standards::impl_non_fungible_token_enumeration!(Contract, tokens);
 
-#[near_bindgen]
-impl Contract {
-    pub fn ticket_contract_metadata(&self) -> TicketContractMetadata {
-        self.metadata.get().unwrap()
+    /// When set, new shows must have a non-empty title and description
+    pub fn set_require_show_details(&mut self, require_show_details: bool) {
+        self.assert_owner();
+        self.require_show_details = require_show_details;
     }
-}
-
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
-#[serde(crate = "near_sdk::serde")]
-pub struct TicketContractMetadata {
-    pub spec: String,   // required, essentially a version like "nft-1.0.0"
-    pub name: String,   // required, ex. "Mosaics"
-    pub symbol: String, // required, ex. "MOSIAC"
-    pub description: Option<String>,
-}
 
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
-#[serde(crate = "near_sdk::serde")]
-pub struct TicketMetadata {
-    pub ticket_id: String,   // required
-    pub show_id: String,     // required,
-    pub ticket_type: String, // required,
-    pub is_used: bool,       // required,
-    issued_at: Timestamp,
-    pub show: Option<ShowMetadata>, // required
-}
+    /// Merge show `source_id` into `target_id`. The first call merges the ticket types,
+    /// capacity pools, revenue, claimed seats and purchase counts and removes the source
+    /// show, every call then rebinds up to `limit` tickets to the target. Types both shows
+    /// have must be configured the same, and the source can not have tickets being minted.
+    /// Returns true, and emits a `show_merged` event, once all tickets of the source show
+    /// are rebound.
+    pub fn merge_shows(&mut self, source_id: String, target_id: String, limit: u64) -> bool {
+        self.assert_owner();
+        let mut merge = match self.show_merges.get(&source_id) {
+            Some(merge) => {
+                assert!(
+                    merge.target_id == target_id,
+                    "Show {} is being merged into {}",
+                    source_id,
+                    merge.target_id
+                );
+                merge
+            }
+            None => {
+                assert!(source_id != target_id, "Cannot merge a show into itself");
+                assert!(
+                    self.show_merges.values().all(|merge| merge.target_id != source_id),
+                    "Show {} is still receiving tickets from another merge",
+                    source_id
+                );
+                self.start_show_merge(&source_id, &target_id);
+                ShowMerge {
+                    target_id: target_id.clone(),
+                    cursor: 0,
+                }
+            }
+        };
 
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
-#[serde(crate = "near_sdk::serde")]
-pub struct TicketInfo {
-    pub supply: u32,         // required
-    pub ticket_type: String, // required,
-    pub price: Balance,
-    pub sold: u32,
-    pub selling_start_time: Option<Timestamp>,
-    pub selling_end_time: Option<Timestamp>,
-}
+        // rebound tickets leave the source set, so the next ones are always at its front
+        let token_ids: Vec<TokenId> = self
+            .tokens_per_show
+            .get(&source_id)
+            .map(|token_ids| token_ids.iter().take(limit as usize).collect())
+            .unwrap_or_default();
+        for token_id in token_ids {
+            let mut ticket = self.tickets.get(&token_id).unwrap();
+            ticket.show_id = target_id.clone();
+            self.tickets.insert(&token_id, &ticket);
+            self.remove_show_token(&source_id, &token_id);
+            self.add_show_token(&target_id, &token_id);
+            merge.cursor += 1;
+        }
 
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+        if self.tokens_per_show.get(&source_id).is_none() {
+            self.show_merges.remove(&source_id);
+            TicketEvent::ShowMerged {
+                source_id,
+                target_id,
+                tickets: merge.cursor,
+            }
+            .emit();
+            true
+        } else {
+            self.show_merges.insert(&source_id, &merge);
+            false
+        }
+    }
+
+    /// Buy one ticket, the promise resolves to the id of the minted ticket, null if the
+    /// mint failed and the deposit was refunded
+    #[payable]
+    pub fn buy_ticket(&mut self, show_id: String, ticket_type: String) -> Promise {
+        let required_gas = self.prepare_gas + CHECK_MINT_GAS + SCHEDULE_GAS;
+        assert!(
+            env::prepaid_gas() - env::used_gas() >= required_gas,
+            "Not enough gas to mint the ticket, please attach more gas (at least {})",
+            required_gas
+        );
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(format!("show {} does not exist", show_id).as_bytes()));
+        self.assert_on_sale(&show, &ticket_type, 1);
+        self.assert_account_limit(&show, &env::predecessor_account_id(), 1);
+        self.assert_presale_access(&show, &env::predecessor_account_id());
+        let info = &show.ticket_infos[&ticket_type];
+        let price = match info.usd_cents {
+            None => info.price.0,
+            Some(usd_cents) => match self.cached_usd_rate() {
+                Some(rate) => usd_cents as Balance * rate,
+                None => {
+                    assert!(
+                        env::prepaid_gas() - env::used_gas() >= required_gas + ORACLE_GAS * 2,
+                        "Not enough gas to fetch the NEAR/USD rate, please attach more gas (at least {})",
+                        required_gas + ORACLE_GAS * 2
+                    );
+                    let oracle = self
+                        .price_oracle
+                        .as_ref()
+                        .unwrap_or_else(|| env::panic(b"Price oracle is not configured"));
+                    return ext_price_oracle::get_near_usd_rate(
+                        &oracle.account_id,
+                        0,
+                        ORACLE_GAS,
+                    )
+                    .then(ex_self::on_usd_rate_for_purchase(
+                        env::predecessor_account_id(),
+                        show_id,
+                        ticket_type,
+                        U128(env::attached_deposit()),
+                        &env::current_account_id(),
+                        0,
+                        env::prepaid_gas() - env::used_gas() - ORACLE_GAS * 2,
+                    ));
+                }
+            },
+        };
+        let total = self.required_deposit(price);
+        assert!(
+            env::attached_deposit() >= total,
+            "Please deposit at least {} (ticket price {} + mint fee {} + storage deposit {}). You deposit {}",
+            total,
+            price,
+            self.mint_fee,
+            TICKET_STORAGE_DEPOSIT,
+            env::attached_deposit()
+        );
+        self.mint_ticket(
+            show_id,
+            ticket_type,
+            env::predecessor_account_id(),
+            price,
+            env::attached_deposit(),
+            None,
+        )
+    }
+
+    /// Buy `amount` tickets of one type, at most `MAX_TICKETS_PER_PURCHASE`. Every ticket is
+    /// minted and refunded on failure on its own, the deposit above the total is refunded
+    /// right away. USD priced tickets need a cached NEAR/USD rate.
+    #[payable]
+    pub fn buy_tickets(&mut self, show_id: String, ticket_type: String, amount: u32) -> Promise {
+        assert!(
+            amount > 0 && amount <= MAX_TICKETS_PER_PURCHASE,
+            "Can buy 1 to {} tickets at once",
+            MAX_TICKETS_PER_PURCHASE
+        );
+        let required_gas = (self.prepare_gas + CHECK_MINT_GAS + SCHEDULE_GAS) * amount as Gas;
+        assert!(
+            env::prepaid_gas() - env::used_gas() >= required_gas,
+            "Not enough gas to mint the tickets, please attach more gas (at least {})",
+            required_gas
+        );
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        self.assert_on_sale(&show, &ticket_type, amount);
+        self.assert_account_limit(&show, &env::predecessor_account_id(), amount);
+        self.assert_presale_access(&show, &env::predecessor_account_id());
+        let info = show.ticket_infos.get(&ticket_type).unwrap();
+        let price = match info.usd_cents {
+            None => info.price.0,
+            Some(usd_cents) => {
+                usd_cents as Balance
+                    * self.cached_usd_rate().unwrap_or_else(|| {
+                        env::panic(b"NEAR/USD rate is not available, buy a single ticket")
+                    })
+            }
+        };
+        let cost = self.required_deposit(price);
+        let total = cost * amount as Balance;
+        assert!(
+            env::attached_deposit() >= total,
+            "Please deposit at least {} for {} tickets ({} each). You deposit {}",
+            total,
+            amount,
+            cost,
+            env::attached_deposit()
+        );
+        let buyer = env::predecessor_account_id();
+        if env::attached_deposit() > total {
+            Promise::new(buyer.clone()).transfer(env::attached_deposit() - total);
+        }
+        let mut purchase =
+            self.mint_ticket(show_id.clone(), ticket_type.clone(), buyer.clone(), price, cost, None);
+        for _ in 1..amount {
+            purchase = purchase.and(self.mint_ticket(
+                show_id.clone(),
+                ticket_type.clone(),
+                buyer.clone(),
+                price,
+                cost,
+                None,
+            ));
+        }
+        purchase
+    }
+
+    /// Buy a ticket for a specific seat, USD priced tickets need a cached NEAR/USD rate.
+    /// A seat can be claimed once per show, it is released again if the mint fails.
+    #[payable]
+    pub fn buy_ticket_with_seat(
+        &mut self,
+        show_id: String,
+        ticket_type: String,
+        seat: String,
+    ) -> Promise {
+        let required_gas = self.prepare_gas + CHECK_MINT_GAS + SCHEDULE_GAS;
+        assert!(
+            env::prepaid_gas() - env::used_gas() >= required_gas,
+            "Not enough gas to mint the ticket, please attach more gas (at least {})",
+            required_gas
+        );
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        self.assert_on_sale(&show, &ticket_type, 1);
+        self.assert_account_limit(&show, &env::predecessor_account_id(), 1);
+        self.assert_presale_access(&show, &env::predecessor_account_id());
+        if let Some(message) = Self::id_error(&seat) {
+            env::panic(format!("seat: {}", message).as_bytes());
+        }
+        let info = show.ticket_infos.get(&ticket_type).unwrap();
+        let price = match info.usd_cents {
+            None => info.price.0,
+            Some(usd_cents) => {
+                usd_cents as Balance
+                    * self.cached_usd_rate().unwrap_or_else(|| {
+                        env::panic(b"NEAR/USD rate is not available, try again later")
+                    })
+            }
+        };
+        let cost = self.required_deposit(price);
+        assert!(
+            env::attached_deposit() >= cost,
+            "Please deposit at least {} (ticket price {} + mint fee {} + storage deposit {}). You deposit {}",
+            cost,
+            price,
+            self.mint_fee,
+            TICKET_STORAGE_DEPOSIT,
+            env::attached_deposit()
+        );
+        assert!(
+            self.claimed_seats.insert(&(show_id.clone(), seat.clone())),
+            "Seat {} is already taken",
+            seat
+        );
+        let buyer = env::predecessor_account_id();
+        if env::attached_deposit() > cost {
+            Promise::new(buyer.clone()).transfer(env::attached_deposit() - cost);
+        }
+        self.mint_ticket(show_id, ticket_type, buyer, price, cost, Some(seat))
+    }
+
+    /// Buy a ticket with the fungible token of its show, `msg` is `{"show_id": ..., "ticket_type": ...}`.
+    /// The contract pays the mint fee and storage from its own balance. Returns the
+    /// unused amount, all of it if the mint failed.
+    pub fn ft_on_transfer(
+        &mut self,
+        sender_id: ValidAccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let purchase: FtPurchase = near_sdk::serde_json::from_str(&msg)
+            .unwrap_or_else(|_| env::panic(b"msg must be a show_id and ticket_type object"));
+        let show = self
+            .shows
+            .get(&purchase.show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        assert!(
+            show.ft_token_id == Some(env::predecessor_account_id()),
+            "Show {} does not accept token {}",
+            purchase.show_id,
+            env::predecessor_account_id()
+        );
+        let buyer: AccountId = sender_id.into();
+        self.assert_on_sale(&show, &purchase.ticket_type, 1);
+        self.assert_account_limit(&show, &buyer, 1);
+        self.assert_presale_access(&show, &buyer);
+        let price = show.ticket_infos[&purchase.ticket_type]
+            .ft_price
+            .unwrap_or_else(|| env::panic(b"This ticket type is not sold for the token"))
+            .0;
+        assert!(
+            amount.0 >= price,
+            "Please transfer at least {}. You transfer {}",
+            price,
+            amount.0
+        );
+        assert!(
+            self.liquid_balance() >= self.mint_fee + TICKET_STORAGE_DEPOSIT,
+            "Not enough balance to pay for the mint"
+        );
+        let serial = self.take_serials(&purchase.show_id, &purchase.ticket_type, 1);
+        let ticket_id = format!("{}.{}.{}", purchase.show_id, purchase.ticket_type, serial);
+        self.add_pending_mint(&purchase.show_id, &purchase.ticket_type);
+        let key = (purchase.show_id.clone(), buyer.clone());
+        let purchases = self.purchases_per_account.get(&key).unwrap_or(0);
+        self.purchases_per_account.insert(&key, &(purchases + 1));
+        log!(
+            "Buy new ticket with {}: ticket id: {}, price: {}",
+            env::predecessor_account_id(),
+            ticket_id,
+            price
+        );
+        ex_self::nft_private_mint(
+            ticket_id,
+            ValidAccountId::try_from(buyer.clone()).unwrap(),
+            None,
+            None,
+            &env::current_account_id(),
+            self.mint_fee,
+            self.prepare_gas,
+        )
+        .then(ex_self::on_ft_mint(
+            buyer,
+            purchase.show_id,
+            purchase.ticket_type,
+            amount,
+            U128(price),
+            &env::current_account_id(),
+            0,
+            CHECK_MINT_GAS,
+        ))
+        .into()
+    }
+
+    /// Return the whole amount if the mint failed, otherwise what is left above the price
+    /// and emit a `ticket_purchased` event with the price in the show's token
+    #[private]
+    pub fn on_ft_mint(
+        &mut self,
+        buyer: AccountId,
+        show_id: String,
+        ticket_type: String,
+        amount: U128,
+        price: U128,
+    ) -> U128 {
+        let value = match env::promise_result(0) {
+            PromiseResult::Successful(value) => value,
+            _ => {
+                self.release_pending_mint(&show_id, &ticket_type);
+                let key = (show_id, buyer.clone());
+                let purchases = self.purchases_per_account.get(&key).unwrap_or(0);
+                self.purchases_per_account.insert(&key, &purchases.saturating_sub(1));
+                log!("Fail to mint new ticket, return {} to {}", amount.0, buyer);
+                return amount;
+            }
+        };
+        let ticket = near_sdk::serde_json::from_slice::<MintedTicket>(&value)
+            .ok()
+            .and_then(|minted| self.tickets.get(&minted.token.token_id));
+        if let Some(ticket) = ticket {
+            TicketEvent::TicketPurchased {
+                buyer,
+                token_id: ticket.ticket_id,
+                show_id: ticket.show_id,
+                ticket_type: ticket.ticket_type,
+                price: price.0,
+                issued_at: ticket.issued_at,
+            }
+            .emit();
+        }
+        let revenue = self.ft_revenue.get(&show_id).unwrap_or(0) + price.0;
+        self.ft_revenue.insert(&show_id, &revenue);
+        U128(amount.0 - price.0)
+    }
+
+    /// Soulbound tickets stay with the account they were minted to
+    pub fn set_show_soulbound(&mut self, show_id: String, soulbound: bool) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        show.soulbound = soulbound;
+        self.shows.insert(&show_id, &show);
+    }
+
+    pub fn set_transfer_lock_time(&mut self, show_id: String, transfer_lock_time: Option<Timestamp>) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        show.transfer_lock_time = transfer_lock_time;
+        self.shows.insert(&show_id, &show);
+    }
+
+    pub fn set_show_ft_token(&mut self, show_id: String, ft_token_id: Option<AccountId>) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        assert!(
+            show.ft_token_id.is_none() || self.ft_revenue.get(&show_id).unwrap_or(0) == 0,
+            "Withdraw the token revenue of show {} first",
+            show_id
+        );
+        show.ft_token_id = ft_token_id;
+        self.shows.insert(&show_id, &show);
+    }
+
+    pub fn get_ft_revenue(&self, show_id: String) -> U128 {
+        U128(self.ft_revenue.get(&show_id).unwrap_or(0))
+    }
+
+    /// Send the token revenue of a show to the owner, who must be registered with the token
+    #[payable]
+    pub fn withdraw_ft_revenue(&mut self, show_id: String) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        let token_id = self
+            .shows
+            .get(&show_id)
+            .and_then(|show| show.ft_token_id)
+            .unwrap_or_else(|| env::panic(b"This show has no fungible token"));
+        let amount = self.ft_revenue.get(&show_id).unwrap_or(0);
+        assert!(amount > 0, "Show {} has no token revenue to withdraw", show_id);
+        self.ft_revenue.remove(&show_id);
+        ext_fungible_token::ft_transfer(
+            self.owner_id.clone(),
+            U128(amount),
+            None,
+            &token_id,
+            1,
+            FT_TRANSFER_GAS,
+        )
+        .then(ex_self::on_ft_revenue_withdrawn(
+            show_id,
+            U128(amount),
+            &env::current_account_id(),
+            0,
+            SCHEDULE_GAS,
+        ))
+    }
+
+    /// Restore the token revenue when the transfer to the owner failed
+    #[private]
+    pub fn on_ft_revenue_withdrawn(&mut self, show_id: String, amount: U128) {
+        if !matches!(env::promise_result(0), PromiseResult::Successful(_)) {
+            let revenue = self.ft_revenue.get(&show_id).unwrap_or(0) + amount.0;
+            self.ft_revenue.insert(&show_id, &revenue);
+        }
+    }
+
+    /// Components of the deposit `buy_ticket` requires for a ticket type
+    pub fn ticket_price_breakdown(&self, show_id: String, ticket_type: String) -> PriceBreakdown {
+        let info = self
+            .shows
+            .get(&show_id)
+            .and_then(|show| show.ticket_infos.get(&ticket_type).cloned())
+            .unwrap_or_else(|| env::panic(b"Ticket type not exist"));
+        let price = match info.usd_cents {
+            None => info.price.0,
+            Some(usd_cents) => {
+                usd_cents as Balance
+                    * self
+                        .cached_usd_rate()
+                        .unwrap_or_else(|| env::panic(b"NEAR/USD rate is not available"))
+            }
+        };
+        PriceBreakdown {
+            ticket_price: U128(price),
+            mint_fee: U128(self.mint_fee),
+            storage_deposit: U128(TICKET_STORAGE_DEPOSIT),
+            total: U128(self.required_deposit(price)),
+        }
+    }
+
+    /// Continue a purchase of a USD priced ticket once the oracle answered.
+    /// The whole deposit is refunded if the rate is unusable or the deposit is too low.
+    #[private]
+    pub fn on_usd_rate_for_purchase(
+        &mut self,
+        buyer: AccountId,
+        show_id: String,
+        ticket_type: String,
+        deposit: U128,
+    ) -> Promise {
+        let rate = self.read_usd_rate_result();
+        let price = rate.and_then(|rate| {
+            self.shows
+                .get(&show_id)
+                .and_then(|show| show.ticket_infos.get(&ticket_type).cloned())
+                .and_then(|info| info.usd_cents)
+                .map(|usd_cents| usd_cents as Balance * rate)
+        });
+        match price {
+            Some(price) if deposit.0 >= self.required_deposit(price) => {
+                self.mint_ticket(show_id, ticket_type, buyer, price, deposit.0, None)
+            }
+            _ => {
+                log!(
+                    "Can not buy ticket {} of show {}, price: {:?} YoctoNear, deposit: {} YoctoNear",
+                    ticket_type,
+                    show_id,
+                    price,
+                    deposit.0
+                );
+                Promise::new(buyer).transfer(deposit.0)
+            }
+        }
+    }
+
+    /// Configure the NEAR/USD feed used for USD priced ticket types. Readings outside of
+    /// `[min_rate, max_rate]` yoctoNEAR per US cent are rejected.
+    pub fn set_price_oracle(&mut self, account_id: ValidAccountId, min_rate: U128, max_rate: U128) {
+        self.assert_owner();
+        assert!(
+            0 < min_rate.0 && min_rate.0 <= max_rate.0,
+            "Invalid rate bounds"
+        );
+        self.price_oracle = Some(PriceOracle {
+            account_id: account_id.into(),
+            min_rate,
+            max_rate,
+        });
+        self.usd_rate = None;
+    }
+
+    pub fn get_price_oracle(&self) -> Option<PriceOracle> {
+        self.price_oracle.clone()
+    }
+
+    /// Last NEAR/USD rate in yoctoNEAR per US cent, if still fresh
+    pub fn get_usd_rate(&self) -> Option<U128> {
+        self.cached_usd_rate().map(U128)
+    }
+
+    #[payable]
+    pub fn check_ticket(&mut self, ticket_id: String) {
+        assert_one_yocto();
+        let owner_id = self.tokens.owner_by_id.get(&ticket_id).unwrap_or_else(|| {
+            env::panic(format!("ticket {} does not exist", ticket_id).as_bytes())
+        });
+        assert!(
+            owner_id == env::predecessor_account_id(),
+            "You do not own the ticket {}",
+            ticket_id
+        );
+        self.check_in(ticket_id);
+    }
+
+    /// Check in up to `MAX_BATCH_SIZE` tickets the caller owns or scans for. A ticket that
+    /// can not be checked in is reported as false and does not stop the others.
+    #[payable]
+    pub fn check_tickets(&mut self, ticket_ids: Vec<String>) -> Vec<(String, bool)> {
+        assert_one_yocto();
+        self.assert_not_paused();
+        assert!(
+            ticket_ids.len() <= MAX_BATCH_SIZE,
+            "Can check at most {} tickets at once",
+            MAX_BATCH_SIZE
+        );
+        let caller = env::predecessor_account_id();
+        ticket_ids
+            .into_iter()
+            .map(|ticket_id| {
+                let allowed = self.tokens.owner_by_id.get(&ticket_id) == Some(caller.clone())
+                    || self
+                        .tickets
+                        .get(&ticket_id)
+                        .is_some_and(|ticket| self.is_scanner(ticket.show_id, caller.clone()));
+                let checked = if allowed {
+                    self.try_check_in(ticket_id.clone())
+                } else {
+                    Err(format!("Caller {} can not check ticket {}", caller, ticket_id))
+                };
+                if let Err(message) = &checked {
+                    log!("{}", message);
+                }
+                (ticket_id, checked.is_ok())
+            })
+            .collect()
+    }
+
+    /// Check in any ticket of a show as one of its scanners, e.g. at the gate.
+    /// Scanners can use a function call access key as no deposit is required.
+    pub fn check_ticket_as_staff(&mut self, ticket_id: String) {
+        let ticket = self
+            .tickets
+            .get(&ticket_id)
+            .unwrap_or_else(|| env::panic(b"ticket id does not exist!"));
+        assert!(
+            self.is_scanner(ticket.show_id.clone(), env::predecessor_account_id()),
+            "Caller {} is not a scanner of show {}",
+            env::predecessor_account_id(),
+            ticket.show_id
+        );
+        self.check_in(ticket_id);
+    }
+
+    pub fn add_scanner(&mut self, show_id: String, account_id: AccountId) {
+        self.assert_owner();
+        assert!(self.shows.get(&show_id).is_some(), "This show not exist");
+        let mut scanners = self.scanners.get(&show_id).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::ShowScanners {
+                show_hash: env::sha256(show_id.as_bytes()),
+            })
+        });
+        scanners.insert(&account_id);
+        self.scanners.insert(&show_id, &scanners);
+    }
+
+    pub fn remove_scanner(&mut self, show_id: String, account_id: AccountId) {
+        self.assert_owner();
+        if let Some(mut scanners) = self.scanners.get(&show_id) {
+            scanners.remove(&account_id);
+            if scanners.is_empty() {
+                self.scanners.remove(&show_id);
+            } else {
+                self.scanners.insert(&show_id, &scanners);
+            }
+        }
+    }
+
+    pub fn is_scanner(&self, show_id: String, account_id: AccountId) -> bool {
+        self.scanners
+            .get(&show_id)
+            .is_some_and(|scanners| scanners.contains(&account_id))
+    }
+
+    pub fn get_scanners(&self, show_id: String) -> Vec<AccountId> {
+        self.scanners
+            .get(&show_id)
+            .map(|scanners| scanners.to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Whether `ticket_id` can still be checked in at the gate
+    pub fn is_ticket_valid(&self, ticket_id: String) -> bool {
+        self.ticket_status(ticket_id) == TicketStatus::Valid
+    }
+
+    pub fn ticket_status(&self, ticket_id: String) -> TicketStatus {
+        let ticket = match self.tickets.get(&ticket_id) {
+            Some(ticket) => ticket,
+            None => return TicketStatus::Unknown,
+        };
+        let show = match self.shows.get(&ticket.show_id) {
+            Some(show) => show,
+            None => return TicketStatus::Unknown,
+        };
+        if show.is_cancelled {
+            TicketStatus::ShowCancelled
+        } else if ticket.entry_count >= self.max_entries_of(&ticket) {
+            TicketStatus::Used
+        } else if env::block_timestamp() > Self::expiry_of(&show) {
+            TicketStatus::Expired
+        } else {
+            TicketStatus::Valid
+        }
+    }
+
+    /// Turn a used ticket into a souvenir NFT once the event has ended, using the
+    /// collectible title and media configured for the show. The attached deposit covers
+    /// any storage increase, the rest is refunded.
+    #[payable]
+    pub fn convert_to_collectible(&mut self, ticket_id: String) {
+        assert!(
+            self.tokens.owner_by_id.get(&ticket_id) == Some(env::predecessor_account_id()),
+            "You do not own the ticket {}",
+            ticket_id
+        );
+        let mut ticket = self
+            .tickets
+            .get(&ticket_id)
+            .unwrap_or_else(|| env::panic(b"ticket id does not exist!"));
+        assert!(ticket.is_used, "Only used tickets can become collectibles");
+        assert!(!ticket.collectible, "Ticket {} is already a collectible", ticket_id);
+        let show = self
+            .shows
+            .get(&ticket.show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        assert!(
+            env::block_timestamp() > Self::expiry_of(&show),
+            "Tickets can become collectibles after the event ends"
+        );
+        let collectible = show
+            .collectible
+            .unwrap_or_else(|| env::panic(b"This show has no collectible"));
+
+        let initial_storage_usage = env::storage_usage();
+        let token_metadata_by_id = self.tokens.token_metadata_by_id.as_mut().unwrap();
+        let mut token_metadata = token_metadata_by_id.get(&ticket_id).unwrap();
+        token_metadata.title = Some(collectible.title);
+        token_metadata.media = Some(collectible.media);
+        token_metadata.media_hash = None;
+        token_metadata.updated_at = Some(env::block_timestamp().to_string());
+        token_metadata_by_id.insert(&ticket_id, &token_metadata);
+        ticket.collectible = true;
+        self.tickets.insert(&ticket_id, &ticket);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        log!("Ticket {} is converted to a collectible", ticket_id);
+    }
+
+    /// Burn a used ticket, or any ticket once the sales of its show ended, and refund the
+    /// released storage to the holder
+    #[payable]
+    pub fn burn_ticket(&mut self, ticket_id: String) -> Promise {
+        assert_one_yocto();
+        assert!(
+            self.tokens.owner_by_id.get(&ticket_id) == Some(env::predecessor_account_id()),
+            "You do not own the ticket {}",
+            ticket_id
+        );
+        let ticket = self
+            .tickets
+            .get(&ticket_id)
+            .unwrap_or_else(|| env::panic(b"ticket id does not exist!"));
+        // burning shifts the tickets `cancel_show_refund_batch` walks through
+        assert!(
+            !self.cancel_refunds_pending(&ticket.show_id),
+            "Show {} is cancelled, tickets can be burned once all holders are refunded",
+            ticket.show_id
+        );
+        let sales_ended = self
+            .shows
+            .get(&ticket.show_id)
+            .is_none_or(|show| env::block_timestamp() >= show.selling_end_time);
+        // a burned ticket stays sold, it frees no capacity for the waitlist
+        assert!(
+            ticket.is_used || sales_ended,
+            "Ticket {} can be burned once it is used or the ticket sales ended",
+            ticket_id
+        );
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = self.burn_token(&ticket_id);
+        self.tickets.remove(&ticket_id);
+        self.remove_show_token(&ticket.show_id, &ticket_id);
+        self.resale_listings.remove(&ticket_id);
+        TicketEvent::NftBurn {
+            owner_id,
+            token_ids: vec![ticket_id.clone()],
+        }
+        .emit();
+        let released = initial_storage_usage.saturating_sub(env::storage_usage());
+        let refund = released as Balance * env::storage_byte_cost() + env::attached_deposit();
+        log!(
+            "Burn ticket {}, refund {} YoctoNear of released storage",
+            ticket_id,
+            refund
+        );
+        Promise::new(env::predecessor_account_id()).transfer(refund)
+    }
+
+    /// Let buyers return unused tickets up to `refund_window_ns` after the purchase,
+    /// None disables returns. The show revenue can not be withdrawn while tickets can be
+    /// returned.
+    pub fn set_refund_window(&mut self, show_id: String, refund_window_ns: Option<Timestamp>) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        show.refund_window_ns = refund_window_ns;
+        self.shows.insert(&show_id, &show);
+    }
+
+    /// Return an unused ticket within the refund window of its show. The ticket is burned,
+    /// goes back on sale and its price and the released storage are refunded to the holder,
+    /// the mint fee is not.
+    #[payable]
+    pub fn refund_ticket(&mut self, ticket_id: String) {
+        assert_one_yocto();
+        self.assert_not_paused();
+        let holder = env::predecessor_account_id();
+        assert!(
+            self.tokens.owner_by_id.get(&ticket_id) == Some(holder.clone()),
+            "You do not own the ticket {}",
+            ticket_id
+        );
+        let ticket = self
+            .tickets
+            .get(&ticket_id)
+            .unwrap_or_else(|| env::panic(b"ticket id does not exist!"));
+        assert!(!ticket.is_used && ticket.entry_count == 0, "Ticket {} is used", ticket_id);
+        let mut show = self.shows.get(&ticket.show_id).unwrap();
+        assert!(!show.is_cancelled, "Show {} is cancelled", show.show_id);
+        let window = show
+            .refund_window_ns
+            .unwrap_or_else(|| env::panic(b"This show does not accept returns"));
+        assert!(
+            env::block_timestamp() <= ticket.issued_at + window,
+            "Refund window of ticket {} is over",
+            ticket_id
+        );
+        let price = ticket
+            .price
+            .unwrap_or_else(|| env::panic(b"Only tickets bought with NEAR can be returned"));
+        // the window was set after the revenue was withdrawn
+        assert!(
+            self.revenue.get(&ticket.show_id).unwrap_or(0) >= price.0,
+            "Revenue of show {} was withdrawn, ticket {} can not be returned",
+            ticket.show_id,
+            ticket_id
+        );
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = self.burn_token(&ticket_id);
+        self.tickets.remove(&ticket_id);
+        self.remove_show_token(&ticket.show_id, &ticket_id);
+        self.resale_listings.remove(&ticket_id);
+        if let Some(seat) = ticket.seat {
+            self.claimed_seats.remove(&(ticket.show_id.clone(), seat));
+        }
+        let info = show.ticket_infos.get_mut(&ticket.ticket_type).unwrap();
+        info.sold = info.sold.saturating_sub(1);
+        self.shows.insert(&ticket.show_id, &show);
+        self.deduct_revenue(&ticket.show_id, price.0);
+        let key = (ticket.show_id.clone(), holder.clone());
+        let purchases = self.purchases_per_account.get(&key).unwrap_or(0);
+        self.purchases_per_account.insert(&key, &purchases.saturating_sub(1));
+        TicketEvent::NftBurn {
+            owner_id,
+            token_ids: vec![ticket_id.clone()],
+        }
+        .emit();
+        let released = initial_storage_usage.saturating_sub(env::storage_usage());
+        let refund =
+            price.0 + released as Balance * env::storage_byte_cost() + env::attached_deposit();
+        log!("Return ticket {}, refund {} YoctoNear", ticket_id, refund);
+        self.refund(holder, refund);
+        self.notify_waitlist(&ticket.show_id, &ticket.ticket_type, 1);
+    }
+
+    /// Wait for a sold out ticket type. Waiting accounts are named in `waitlist_notified`
+    /// events in the order they joined when tickets of the type are burned or its supply
+    /// is increased. The storage is paid from the deposit, the rest is refunded.
+    #[payable]
+    pub fn join_waitlist(&mut self, show_id: String, ticket_type: String) {
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        assert!(show.ticket_infos.contains_key(&ticket_type), "This ticket is not exist");
+        assert!(
+            self.remaining_of(&show, &ticket_type) == 0,
+            "Ticket type {} is not sold out",
+            ticket_type
+        );
+        let initial_storage_usage = env::storage_usage();
+        let key = Self::ticket_type_key(&show_id, &ticket_type);
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.waitlisted.insert(&(key.clone(), account_id.clone())),
+            "You are already on the waitlist of {}",
+            key
+        );
+        let mut waitlist = self.waitlists.get(&key).unwrap_or_else(|| Waitlist {
+            accounts: Vector::new(StorageKey::TypeWaitlist {
+                type_hash: env::sha256(key.as_bytes()),
+            }),
+            next: 0,
+        });
+        waitlist.accounts.push(&account_id);
+        self.waitlists.insert(&key, &waitlist);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Accounts still waiting for `ticket_type`, in the order they will be notified
+    pub fn waitlist(
+        &self,
+        show_id: String,
+        ticket_type: String,
+        from_index: u64,
+        limit: u64,
+    ) -> Vec<AccountId> {
+        let key = Self::ticket_type_key(&show_id, &ticket_type);
+        let waitlist = match self.waitlists.get(&key) {
+            Some(waitlist) => waitlist,
+            None => return Vec::new(),
+        };
+        let start = waitlist.next + from_index;
+        let end = std::cmp::min(start + limit.min(MAX_BATCH_SIZE as u64), waitlist.accounts.len());
+        (start..end)
+            .filter_map(|index| waitlist.accounts.get(index))
+            .collect()
+    }
+
+    pub fn set_show_collectible(&mut self, show_id: String, collectible: Option<CollectibleMetadata>) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        show.collectible = collectible;
+        self.shows.insert(&show_id, &show);
+    }
+
+    /// Revert a check-in that was recorded by mistake, e.g. after a gate system failure
+    pub fn uncheck_ticket(&mut self, ticket_id: String, reason: String) {
+        self.assert_owner();
+        assert!(!reason.is_empty(), "Please provide a reason");
+        let mut ticket = self
+            .tickets
+            .get(&ticket_id)
+            .unwrap_or_else(|| env::panic(b"ticket id does not exist!"));
+        assert!(ticket.entry_count > 0, "Ticket {} is not checked", ticket_id);
+        ticket.entry_count -= 1;
+        ticket.is_used = ticket.entry_count > 0;
+        if !ticket.is_used {
+            self.tickets_checked = self.tickets_checked.saturating_sub(1);
+        }
+        ticket.unchecked_reason = Some(reason.clone());
+        self.tickets.insert(&ticket_id, &ticket);
+        log!("Ticket {} is unchecked: {}", ticket_id, reason);
+    }
+
+    /// Share of the ticket price refunded by `refund_checkin_failure`, in basis points
+    pub fn set_checkin_refund_bps(&mut self, bps: u16) {
+        self.assert_owner();
+        assert!(bps <= BPS_DENOMINATOR, "Refund can not exceed the ticket price");
+        self.checkin_refund_bps = bps;
+    }
+
+    /// Fee charged on top of the ticket price and attached to every mint, at least
+    /// MIN_MINT_FEE. Mints already scheduled keep the fee they were scheduled with.
+    pub fn set_mint_fee(&mut self, fee: U128) {
+        self.assert_owner();
+        assert!(fee.0 >= MIN_MINT_FEE, "Mint fee must be at least {}", MIN_MINT_FEE);
+        self.mint_fee = fee.0;
+    }
+
+    pub fn get_mint_fee(&self) -> U128 {
+        U128(self.mint_fee)
+    }
+
+    /// Gas attached to every mint, a purchase of `MAX_TICKETS_PER_PURCHASE` tickets must
+    /// still fit in a transaction
+    pub fn set_prepare_gas(&mut self, gas: U64) {
+        self.assert_owner();
+        let max_gas =
+            MAX_PREPAID_GAS / MAX_TICKETS_PER_PURCHASE as Gas - CHECK_MINT_GAS - SCHEDULE_GAS;
+        assert!(
+            gas.0 >= MIN_PREPARE_GAS && gas.0 <= max_gas,
+            "Prepare gas must be between {} and {}",
+            MIN_PREPARE_GAS,
+            max_gas
+        );
+        self.prepare_gas = gas.0;
+    }
+
+    pub fn get_prepare_gas(&self) -> U64 {
+        U64(self.prepare_gas)
+    }
+
+    /// Refund the holder of a ticket that was unchecked because of a check-in failure.
+    /// Each ticket can only be refunded once.
+    pub fn refund_checkin_failure(&mut self, ticket_id: String) {
+        self.assert_owner();
+        let mut ticket = self
+            .tickets
+            .get(&ticket_id)
+            .unwrap_or_else(|| env::panic(b"ticket id does not exist!"));
+        assert!(
+            ticket.unchecked_reason.is_some(),
+            "Ticket {} was never unchecked",
+            ticket_id
+        );
+        assert!(
+            !ticket.checkin_refunded,
+            "Ticket {} is already refunded",
+            ticket_id
+        );
+        let price = self
+            .shows
+            .get(&ticket.show_id)
+            .and_then(|show| show.ticket_infos.get(&ticket.ticket_type).cloned())
+            .map(|info| info.price.0)
+            .unwrap_or_else(|| env::panic(b"Ticket type not exist"));
+        let amount = price * self.checkin_refund_bps as Balance / BPS_DENOMINATOR as Balance;
+        let holder = self.tokens.owner_by_id.get(&ticket_id).unwrap();
+        ticket.checkin_refunded = true;
+        self.tickets.insert(&ticket_id, &ticket);
+        log!(
+            "Check-in failure refund: ticket id: {}, holder: {}, amount: {} YoctoNear, reason: {}",
+            ticket_id,
+            holder,
+            amount,
+            ticket.unchecked_reason.unwrap()
+        );
+        self.deduct_revenue(&ticket.show_id, amount);
+        self.refund(holder, amount);
+    }
+
+    /// Cancel a show, which stops sales and check-ins. Holders of unused tickets get the
+    /// face value back through `cancel_show_refund_batch`.
+    pub fn cancel_show(&mut self, show_id: String) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        assert!(!show.is_cancelled, "Show {} is already cancelled", show_id);
+        show.is_cancelled = true;
+        self.shows.insert(&show_id, &show);
+        log!("Show {} is cancelled", show_id);
+    }
+
+    /// Move the sales window of a show, only the given bounds change. Once sales started
+    /// the start can not move earlier.
+    pub fn update_show_times(
+        &mut self,
+        show_id: String,
+        selling_start_time: Option<Timestamp>,
+        selling_end_time: Option<Timestamp>,
+    ) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        if let Some(selling_start_time) = selling_start_time {
+            assert!(
+                env::block_timestamp() <= show.selling_start_time
+                    || selling_start_time >= show.selling_start_time,
+                "Sales of show {} already started, the start can not move earlier",
+                show_id
+            );
+            show.selling_start_time = selling_start_time;
+        }
+        if let Some(selling_end_time) = selling_end_time {
+            show.selling_end_time = selling_end_time;
+        }
+        assert!(
+            show.selling_start_time < show.selling_end_time,
+            "Selling end time must be after the start time"
+        );
+        self.shows.insert(&show_id, &show);
+        log!(
+            "Show {} sells tickets from {} to {}",
+            show_id,
+            show.selling_start_time,
+            show.selling_end_time
+        );
+    }
+
+    /// Set when the event takes place, tickets stay valid until `event_end_time`
+    pub fn set_event_times(
+        &mut self,
+        show_id: String,
+        event_start_time: Option<Timestamp>,
+        event_end_time: Option<Timestamp>,
+    ) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        if let (Some(start), Some(end)) = (event_start_time, event_end_time) {
+            assert!(start < end, "Event end time must be after the start time");
+        }
+        show.event_start_time = event_start_time;
+        show.event_end_time = event_end_time;
+        self.shows.insert(&show_id, &show);
+    }
+
+    /// Delete a show that never sold a ticket and refund the released storage to the owner.
+    /// Shows with sales can only be cancelled with `cancel_show`.
+    pub fn remove_show(&mut self, show_id: String) -> Promise {
+        self.assert_owner();
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        assert!(
+            show.ticket_infos.values().all(|info| info.sold == 0)
+                && self.tokens_per_show.get(&show_id).is_none()
+                && show
+                    .ticket_infos
+                    .keys()
+                    .all(|ticket_type| self.reserved_tickets(&show_id, ticket_type) == 0),
+            "Show {} has sold tickets, use cancel_show instead",
+            show_id
+        );
+        let initial_storage_usage = env::storage_usage();
+        self.shows.remove(&show_id);
+        for ticket_type in show.ticket_infos.keys() {
+            self.next_serials.remove(&Self::ticket_type_key(&show_id, ticket_type));
+        }
+        if let Some(mut allowlist) = self.allowlists.remove(&show_id) {
+            allowlist.clear();
+        }
+        if let Some(mut scanners) = self.scanners.remove(&show_id) {
+            scanners.clear();
+        }
+        let released = initial_storage_usage.saturating_sub(env::storage_usage());
+        let refund = released as Balance * env::storage_byte_cost();
+        log!("Remove show {}, refund {} YoctoNear of released storage", show_id, refund);
+        Promise::new(self.owner_id.clone()).transfer(refund)
+    }
+
+    /// Refund the holders of the next `limit` tickets of a cancelled show the price they
+    /// paid. Returns true once every ticket is processed, tickets of the show can not be
+    /// burned before.
+    pub fn cancel_show_refund_batch(&mut self, show_id: String, limit: u32) -> bool {
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        assert!(show.is_cancelled, "Show {} is not cancelled", show_id);
+        let token_ids = match self.tokens_per_show.get(&show_id) {
+            Some(token_ids) => token_ids,
+            None => return true,
+        };
+        let from_index = self.cancel_refund_progress.get(&show_id).unwrap_or(0);
+        let end = std::cmp::min(
+            from_index + limit.min(MAX_BATCH_SIZE as u32) as u64,
+            token_ids.len(),
+        );
+        for index in from_index..end {
+            let token_id = token_ids.as_vector().get(index).unwrap();
+            let ticket = match self.tickets.get(&token_id) {
+                Some(ticket) => ticket,
+                None => continue,
+            };
+            if ticket.is_used || ticket.checkin_refunded {
+                continue;
+            }
+            // the price paid, tickets minted without NEAR have nothing to refund
+            let price = match ticket.price {
+                Some(price) => price.0,
+                None => continue,
+            };
+            let holder = self.tokens.owner_by_id.get(&token_id).unwrap();
+            log!(
+                "Cancellation refund: ticket id: {}, holder: {}, amount: {} YoctoNear",
+                token_id,
+                holder,
+                price
+            );
+            self.deduct_revenue(&show_id, price);
+            self.refund(holder, price);
+        }
+        self.cancel_refund_progress.insert(&show_id, &end);
+        end == token_ids.len()
+    }
+
+    pub fn is_cancelled(&self, show_id: String) -> bool {
+        self.shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"))
+            .is_cancelled
+    }
+
+    /// Send the collected face value of a show's ticket sales to the owner, or shared by
+    /// the splits of the show with the rounding left over going to the owner. Fees and
+    /// storage deposits are not part of the revenue.
+    pub fn withdraw_revenue(&mut self, show_id: String) -> Promise {
+        self.assert_owner();
+        let amount = self.revenue.get(&show_id).unwrap_or(0);
+        assert!(amount > 0, "Show {} has no revenue to withdraw", show_id);
+        let returns_until = self.shows.get(&show_id).and_then(|show| Self::returns_until(&show));
+        if let Some(until) = returns_until {
+            assert!(
+                env::block_timestamp() > until,
+                "Tickets of show {} can be returned until {}, its revenue is locked until then",
+                show_id,
+                until
+            );
+        }
+        assert!(
+            !self.revenue_locked(&show_id),
+            "Revenue of show {} is locked until its event ends",
+            show_id
+        );
+        assert!(
+            self.liquid_balance() >= amount,
+            "Not enough balance above the storage reserve and pending refunds to withdraw {}",
+            amount
+        );
+        self.revenue.remove(&show_id);
+        log!("Withdraw revenue of show {}: {} YoctoNear", show_id, amount);
+        let splits = self.shows.get(&show_id).map(|show| show.splits).unwrap_or_default();
+        let mut paid: Balance = 0;
+        let mut payouts: Option<Promise> = None;
+        for (account_id, bps) in splits {
+            let share = amount * bps as Balance / BPS_DENOMINATOR as Balance;
+            paid += share;
+            let payout = Promise::new(account_id).transfer(share);
+            payouts = Some(match payouts {
+                Some(payouts) => payouts.and(payout),
+                None => payout,
+            });
+        }
+        let rest = Promise::new(self.owner_id.clone()).transfer(amount - paid);
+        match payouts {
+            Some(payouts) => payouts.and(rest),
+            None => rest,
+        }
+    }
+
+    pub fn get_revenue(&self, show_id: String) -> U128 {
+        U128(self.revenue.get(&show_id).unwrap_or(0))
+    }
+
+    /// Keep the revenue of a show for refunds until its event ends
+    pub fn set_proceeds_locked_until_event(&mut self, show_id: String, locked: bool) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        show.proceeds_locked_until_event = locked;
+        self.shows.insert(&show_id, &show);
+    }
+
+    pub fn revenue_status(&self, show_id: String) -> RevenueStatus {
+        let amount = U128(self.revenue.get(&show_id).unwrap_or(0));
+        if self.revenue_locked(&show_id) {
+            RevenueStatus {
+                locked: amount,
+                withdrawable: U128(0),
+            }
+        } else {
+            RevenueStatus {
+                locked: U128(0),
+                withdrawable: amount,
+            }
+        }
+    }
+
+    /// Gross NEAR sales of a show, ticket prices plus mint fees of every successful
+    /// mint. Unlike `get_revenue` it is not reduced by withdrawals or refunds.
+    pub fn show_revenue(&self, show_id: String) -> U128 {
+        U128(self.gross_revenue.get(&show_id).unwrap_or(0))
+    }
+
+    /// Pay out a refund that was recorded while the contract balance was too low
+    pub fn claim_refund(&mut self) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .pending_refunds
+            .get(&account_id)
+            .unwrap_or_else(|| env::panic(b"No pending refund"));
+        self.pending_refunds.remove(&account_id);
+        self.total_pending_refunds -= amount;
+        assert!(
+            self.liquid_balance() >= amount,
+            "Not enough funds to pay the refund yet, please try again later"
+        );
+        log!("Claim pending refund: {} YoctoNear to {}", amount, account_id);
+        Promise::new(account_id).transfer(amount)
+    }
+
+    pub fn pending_refund(&self, account_id: AccountId) -> U128 {
+        U128(self.pending_refunds.get(&account_id).unwrap_or(0))
+    }
+
+    /// Sum of the recorded refunds the owner has to top the contract up for
+    pub fn total_pending_refunds(&self) -> U128 {
+        U128(self.total_pending_refunds)
+    }
+    #[payable]
+    #[private]
+    pub fn nft_private_mint(
+        &mut self,
+        token_id: TokenId,
+        receiver_id: ValidAccountId,
+        seat: Option<String>,
+        price: Option<U128>,
+    ) -> MintedTicket {
+        assert!(self.tickets.get(&token_id).is_none(), "Ticket {} is already minted", token_id);
+        let initial_storage_usage = env::storage_usage();
+        let token_id_split: Vec<&str> = token_id.split(".").collect();
+        let show_id = token_id_split[0].to_string();
+        let ticket_type = token_id_split[1].to_string();
+        self.release_pending_mint(&show_id, &ticket_type);
+        self.record_sale(&show_id, &ticket_type);
+        let token = self.mint_ticket_token(
+            TicketMetadata {
+                ticket_id: token_id,
+                show_id,
+                ticket_type,
+                is_used: false,
+                entry_count: 0,
+                unchecked_reason: None,
+                checkin_refunded: false,
+                issued_at: env::block_timestamp(),
+                show: None,
+                reissued_from: None,
+                collectible: false,
+                seat,
+                price,
+            },
+            receiver_id,
+        );
+        MintedTicket {
+            token,
+            storage_usage: U64(env::storage_usage().saturating_sub(initial_storage_usage)),
+        }
+    }
+
+    /// Mint `count` tickets with consecutive serials to `receiver`, e.g. for a distributor.
+    /// The serials and the supply are reserved up front, at most `MAX_BLOCK_MINTS_PER_CALL`
+    /// tickets are minted per call and `continue_block_allocation` mints the rest. The
+    /// attached deposit covers the storage of the tickets minted by this call.
+    #[payable]
+    pub fn allocate_block(
+        &mut self,
+        show_id: String,
+        ticket_type: String,
+        count: u32,
+        receiver: ValidAccountId,
+    ) -> Promise {
+        self.assert_owner();
+        assert!(count > 0, "Block must contain at least one ticket");
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        assert!(!show.is_cancelled, "This show is cancelled");
+        let remaining = self.remaining_of(&show, &ticket_type);
+        assert!(count <= remaining, "Only {} tickets left", remaining);
+        let reserved = self.reserved_tickets(&show_id, &ticket_type);
+        let first_serial = self.take_serials(&show_id, &ticket_type, count);
+        self.reserved_tickets
+            .insert(&Self::ticket_type_key(&show_id, &ticket_type), &(reserved + count));
+        let block_id = self.block_allocations.len();
+        self.block_allocations.push(&BlockAllocation {
+            show_id,
+            ticket_type,
+            receiver: receiver.into(),
+            first_serial,
+            count,
+            scheduled: 0,
+            minted: 0,
+            allocated_at: env::block_timestamp(),
+            failed_serials: vec![],
+        });
+        let block = self.block_allocations.get(block_id).unwrap();
+        log!(
+            "Allocate block {}: {} {} tickets of show {} with serials {} to {} to {}",
+            block_id,
+            count,
+            block.ticket_type,
+            block.show_id,
+            first_serial,
+            first_serial + count - 1,
+            block.receiver
+        );
+        self.schedule_block_mints(block_id)
+    }
+
+    /// Mint the next tickets of a block allocation
+    #[payable]
+    pub fn continue_block_allocation(&mut self, block_id: u64) -> Promise {
+        self.assert_owner();
+        self.schedule_block_mints(block_id)
+    }
+
+    #[payable]
+    #[private]
+    pub fn nft_block_mint(&mut self, block_id: u64, serial: u32) -> Token {
+        let mut block = self.block_allocations.get(block_id).unwrap();
+        let key = Self::ticket_type_key(&block.show_id, &block.ticket_type);
+        let reserved = self.reserved_tickets.get(&key).unwrap_or(0);
+        self.reserved_tickets.insert(&key, &reserved.saturating_sub(1));
+        self.record_sale(&block.show_id, &block.ticket_type);
+        block.minted += 1;
+        self.block_allocations.replace(block_id, &block);
+        self.mint_ticket_token(
+            TicketMetadata {
+                ticket_id: format!("{}.{}.{}", block.show_id, block.ticket_type, serial),
+                show_id: block.show_id,
+                ticket_type: block.ticket_type,
+                is_used: false,
+                entry_count: 0,
+                unchecked_reason: None,
+                checkin_refunded: false,
+                issued_at: env::block_timestamp(),
+                show: None,
+                reissued_from: None,
+                collectible: false,
+                seat: None,
+                price: None,
+            },
+            ValidAccountId::try_from(block.receiver).unwrap(),
+        )
+    }
+
+    /// Queue the serial of a failed block mint for `continue_block_allocation`, its
+    /// reservation is kept, and refund the deposit of the mint to `payer`
+    #[private]
+    pub fn on_block_mint(
+        &mut self,
+        block_id: u64,
+        serial: u32,
+        payer: AccountId,
+        mint_fee: U128,
+    ) -> bool {
+        if matches!(env::promise_result(0), PromiseResult::Successful(_)) {
+            return true;
+        }
+        let mut block = self.block_allocations.get(block_id).unwrap();
+        block.failed_serials.push(serial);
+        self.block_allocations.replace(block_id, &block);
+        log!("Mint of serial {} of block {} failed, it can be retried", serial, block_id);
+        self.refund(payer, mint_fee.0 + TICKET_STORAGE_DEPOSIT);
+        false
+    }
+
+    pub fn get_block_allocation(&self, block_id: u64) -> Option<BlockAllocation> {
+        self.block_allocations.get(block_id)
+    }
+
+    pub fn get_block_allocations(&self, from_index: u64, limit: u64) -> Vec<BlockAllocation> {
+        let end = std::cmp::min(from_index + limit, self.block_allocations.len());
+        (from_index..end)
+            .filter_map(|index| self.block_allocations.get(index))
+            .collect()
+    }
+
+    /// Replace `old_token_id` with a new ticket of the same show and type owned by
+    /// `new_owner`, e.g. when the original was lost or stolen. The attached deposit
+    /// covers the storage of the new ticket. The new ticket keeps the check-in state of the
+    /// old one, a used ticket can not be reissued as an unused one.
+    #[payable]
+    pub fn reissue_ticket(&mut self, old_token_id: TokenId, new_owner: ValidAccountId) -> Promise {
+        self.assert_owner();
+        assert!(
+            env::attached_deposit() >= self.mint_fee,
+            "Please deposit at least {} to cover the new ticket",
+            self.mint_fee
+        );
+        let old_ticket = self
+            .tickets
+            .get(&old_token_id)
+            .unwrap_or_else(|| env::panic(b"ticket id does not exist!"));
+        assert!(
+            !self.is_cancelled(old_ticket.show_id),
+            "This show is cancelled"
+        );
+        ex_self::nft_reissue_mint(
+            old_token_id,
+            new_owner,
+            &env::current_account_id(),
+            env::attached_deposit(),
+            self.prepare_gas,
+        )
+    }
+
+    #[payable]
+    #[private]
+    pub fn nft_reissue_mint(&mut self, old_token_id: TokenId, new_owner: ValidAccountId) -> Token {
+        let old_ticket = self
+            .tickets
+            .get(&old_token_id)
+            .unwrap_or_else(|| env::panic(b"ticket id does not exist!"));
+        let token_id = format!("{}.reissue", old_token_id);
+        assert!(
+            self.tickets.get(&token_id).is_none(),
+            "Ticket {} is already reissued",
+            old_token_id
+        );
+        self.burn_token(&old_token_id);
+        self.tickets.remove(&old_token_id);
+        self.remove_show_token(&old_ticket.show_id, &old_token_id);
+        log!(
+            "Reissue ticket {} as {} to {}",
+            old_token_id,
+            token_id,
+            new_owner.as_ref()
+        );
+        self.mint_ticket_token(
+            TicketMetadata {
+                ticket_id: token_id,
+                show_id: old_ticket.show_id,
+                ticket_type: old_ticket.ticket_type,
+                // the replacement is as used as the original, its entries are not reset
+                is_used: old_ticket.is_used,
+                entry_count: old_ticket.entry_count,
+                unchecked_reason: old_ticket.unchecked_reason,
+                checkin_refunded: old_ticket.checkin_refunded,
+                issued_at: env::block_timestamp(),
+                show: None,
+                reissued_from: Some(old_token_id),
+                collectible: false,
+                seat: old_ticket.seat,
+                price: old_ticket.price,
+            },
+            new_owner,
+        )
+    }
+
+    /// Refund the whole deposit if the mint failed, otherwise what is left above the
+    /// price, the mint fee and the storage the mint used, and emit a `ticket_purchased`
+    /// event for the minted ticket:
+    ///
+    /// `EVENT_JSON:{"data":[{"buyer":"alice.near","issued_at":"1640000000000000000","price":"1000000000000000000000000","show_id":"show","ticket_type":"vip","token_id":"show.vip.0"}],"event":"ticket_purchased","standard":"ticket","version":"1.0.0"}`
+    ///
+    /// `price` is the face value in yoctoNEAR and `issued_at` the mint time in nanoseconds,
+    /// both as strings. Returns the id of the minted ticket, so it is the result of the
+    /// purchase promise, None if the mint failed.
+    #[private]
+    #[allow(clippy::too_many_arguments)]
+    pub fn check_mint(
+        &mut self,
+        buyer: AccountId,
+        show_id: String,
+        ticket_type: String,
+        deposit: Balance,
+        price: Balance,
+        mint_fee: Balance,
+        seat: Option<String>,
+    ) -> Option<TokenId> {
+        let mut result: bool = true;
+        for i in 0..env::promise_results_count() {
+            if env::promise_result(i) == PromiseResult::Failed {
+                result = false;
+                break;
+            }
+        }
+        if !result {
+            self.release_pending_mint(&show_id, &ticket_type);
+            if let Some(seat) = seat {
+                self.claimed_seats.remove(&(show_id.clone(), seat));
+            }
+            let key = (show_id, buyer.clone());
+            let purchases = self.purchases_per_account.get(&key).unwrap_or(0);
+            self.purchases_per_account.insert(&key, &purchases.saturating_sub(1));
+            log!("Fail to mint new ticket, refund {} YoctoNear to {}", deposit, buyer);
+            Promise::new(buyer).transfer(deposit);
+            return None;
+        }
+        let minted = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<MintedTicket>(&value).ok()
+            }
+            _ => None,
+        };
+        let storage_cost = minted.as_ref().map_or(TICKET_STORAGE_DEPOSIT, |minted| {
+            std::cmp::min(
+                minted.storage_usage.0 as Balance * env::storage_byte_cost(),
+                TICKET_STORAGE_DEPOSIT,
+            )
+        });
+        let token_id = minted.map(|minted| minted.token.token_id);
+        if let Some(ticket) = token_id.as_ref().and_then(|token_id| self.tickets.get(token_id)) {
+            TicketEvent::TicketPurchased {
+                buyer: buyer.clone(),
+                token_id: ticket.ticket_id,
+                show_id: ticket.show_id,
+                ticket_type: ticket.ticket_type,
+                price,
+                issued_at: ticket.issued_at,
+            }
+            .emit();
+        }
+        let revenue = self.revenue.get(&show_id).unwrap_or(0) + price;
+        self.revenue.insert(&show_id, &revenue);
+        let gross_revenue = self.gross_revenue.get(&show_id).unwrap_or(0) + price + mint_fee;
+        self.gross_revenue.insert(&show_id, &gross_revenue);
+        let cost = price + mint_fee + storage_cost;
+        if deposit > cost {
+            Promise::new(buyer).transfer(deposit - cost);
+        }
+        token_id
+    }
+
+    /// Sale state of a show and the seconds left until its window opens and closes
+    pub fn sale_countdown(&self, show_id: String) -> SaleTiming {
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        let now = env::block_timestamp();
+        let seconds_until = |time: Timestamp| (time - now) / 1_000_000_000;
+        if now <= show.selling_start_time {
+            SaleTiming {
+                state: SaleState::NotStarted,
+                seconds_until_start: Some(seconds_until(show.selling_start_time)),
+                seconds_until_end: Some(seconds_until(show.selling_end_time.max(now))),
+            }
+        } else if now < show.selling_end_time {
+            SaleTiming {
+                state: SaleState::Active,
+                seconds_until_start: None,
+                seconds_until_end: Some(seconds_until(show.selling_end_time)),
+            }
+        } else {
+            SaleTiming {
+                state: SaleState::Ended,
+                seconds_until_start: None,
+                seconds_until_end: None,
+            }
+        }
+    }
+
+    pub fn get_active_shows(&self) -> Vec<ShowMetadata> {
+        self.shows
+            .values()
+            .filter_map(|show| {
+                if show.published
+                    && show.selling_start_time < env::block_timestamp()
+                    && show.selling_end_time > env::block_timestamp()
+                {
+                    Some(show)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Remaining tickets of a type for display, a range like "10-50" when the show hides
+    /// exact counts
+    pub fn remaining_tickets_label(&self, show_id: String, ticket_type: String) -> String {
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        let remaining = self.remaining_of(&show, &ticket_type);
+        if !show.hide_exact_remaining {
+            return remaining.to_string();
+        }
+        Self::remaining_range(remaining).1.to_string()
+    }
+
+    /// Tickets of a type that can still be bought. Shows that hide exact counts answer
+    /// the lowest count of the range `remaining_tickets_label` shows, ex. 10 for "10-50".
+    pub fn remaining_tickets(&self, show_id: String, ticket_type: String) -> u32 {
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        self.visible_remaining_of(&show, &ticket_type)
+    }
+
+    /// `remaining_tickets` of every ticket type of a show
+    pub fn remaining_tickets_all(&self, show_id: String) -> HashMap<String, u32> {
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        show.ticket_infos
+            .keys()
+            .map(|ticket_type| (ticket_type.clone(), self.visible_remaining_of(&show, ticket_type)))
+            .collect()
+    }
+
+    /// Exact remaining tickets for the owner of a show that hides them. Views can not
+    /// check the caller, so it is a change method. Note that the counts can still be
+    /// derived from the public contract state.
+    pub fn remaining_tickets_exact(&mut self, show_id: String, ticket_type: String) -> u32 {
+        self.assert_owner();
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        self.remaining_of(&show, &ticket_type)
+    }
+
+    pub fn set_hide_exact_remaining(&mut self, show_id: String, hide_exact_remaining: bool) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        show.hide_exact_remaining = hide_exact_remaining;
+        self.shows.insert(&show_id, &show);
+    }
+
+    pub fn set_max_per_account(&mut self, show_id: String, max_per_account: Option<u32>) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        show.max_per_account = max_per_account;
+        self.shows.insert(&show_id, &show);
+    }
+
+    pub fn set_presale_end_time(&mut self, show_id: String, presale_end_time: Option<Timestamp>) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        show.presale_end_time = presale_end_time;
+        self.shows.insert(&show_id, &show);
+    }
+
+    /// Allow `accounts` to buy during the presale of a show, at most `MAX_BATCH_SIZE` per call.
+    /// Accounts already on the allowlist are skipped, so large allowlists can be built with
+    /// repeated calls. Returns how many accounts were added.
+    pub fn add_to_allowlist(&mut self, show_id: String, accounts: Vec<AccountId>) -> u32 {
+        self.assert_owner();
+        assert!(self.shows.get(&show_id).is_some(), "This show not exist");
+        assert!(
+            accounts.len() <= MAX_BATCH_SIZE,
+            "Can add at most {} accounts at once",
+            MAX_BATCH_SIZE
+        );
+        let mut allowlist = self.allowlists.get(&show_id).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::ShowAllowlist {
+                show_hash: env::sha256(show_id.as_bytes()),
+            })
+        });
+        let mut added = 0;
+        for account_id in accounts.iter() {
+            if allowlist.insert(account_id) {
+                added += 1;
+            }
+        }
+        self.allowlists.insert(&show_id, &allowlist);
+        log!(
+            "Add {} accounts to the allowlist of show {}, {} in total",
+            added,
+            show_id,
+            allowlist.len()
+        );
+        added
+    }
+
+    pub fn remove_from_allowlist(&mut self, show_id: String, accounts: Vec<AccountId>) {
+        self.assert_owner();
+        assert!(
+            accounts.len() <= MAX_BATCH_SIZE,
+            "Can remove at most {} accounts at once",
+            MAX_BATCH_SIZE
+        );
+        if let Some(mut allowlist) = self.allowlists.get(&show_id) {
+            for account_id in accounts.iter() {
+                allowlist.remove(account_id);
+            }
+            if allowlist.is_empty() {
+                self.allowlists.remove(&show_id);
+            } else {
+                self.allowlists.insert(&show_id, &allowlist);
+            }
+        }
+    }
+
+    /// Accounts on the presale allowlist of a show
+    pub fn presale_size(&self, show_id: String) -> u64 {
+        self.allowlists
+            .get(&show_id)
+            .map_or(0, |allowlist| allowlist.len())
+    }
+
+    pub fn is_allowlisted(&self, show_id: String, account_id: AccountId) -> bool {
+        self.allowlists
+            .get(&show_id)
+            .is_some_and(|allowlist| allowlist.contains(&account_id))
+    }
+
+    /// Tickets `account_id` bought of a show, including purchases still being minted
+    pub fn purchases_by_account(&self, show_id: String, account_id: AccountId) -> u32 {
+        self.purchases_per_account
+            .get(&(show_id, account_id))
+            .unwrap_or(0)
+    }
+
+    /// Tickets left in a capacity pool, shared by all ticket types of the show that
+    /// reference it
+    pub fn pool_remaining(&self, show_id: String, pool: String) -> u32 {
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        self.pool_remaining_of(&show, &pool)
+    }
+
+    /// Add a capacity pool or change its capacity, which can not go below the tickets
+    /// already sold or reserved from it
+    pub fn set_capacity_pool(&mut self, show_id: String, pool: String, capacity: u32) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        if let Some(message) = Self::id_error(&pool) {
+            env::panic(format!("pool: {}", message).as_bytes());
+        }
+        if show.capacity_pools.contains_key(&pool) {
+            let used = show.capacity_pools[&pool] - self.pool_remaining_of(&show, &pool);
+            assert!(capacity >= used, "{} tickets of pool {} are already taken", used, pool);
+        }
+        show.capacity_pools.insert(pool, capacity);
+        self.shows.insert(&show_id, &show);
+    }
+
+    /// False for an unknown show or ticket type
+    pub fn is_type_sold_out(&self, show_id: String, ticket_type: String) -> bool {
+        self.shows.get(&show_id).is_some_and(|show| {
+            show.ticket_infos.contains_key(&ticket_type)
+                && self.remaining_of(&show, &ticket_type) == 0
+        })
+    }
+
+    /// True when every ticket type of the show is sold out
+    pub fn is_show_sold_out(&self, show_id: String) -> bool {
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        show.ticket_infos
+            .keys()
+            .all(|ticket_type| self.remaining_of(&show, ticket_type) == 0)
+    }
+
+    pub fn sold_out_types(&self, show_id: String) -> Vec<String> {
+        let show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        let mut ticket_types: Vec<String> = show
+            .ticket_infos
+            .keys()
+            .filter(|ticket_type| self.remaining_of(&show, ticket_type) == 0)
+            .cloned()
+            .collect();
+        ticket_types.sort();
+        ticket_types
+    }
+
+    /// Every show in one call, unbounded, so prefer `get_shows` once there are many shows
+    pub fn get_all_shows(&self) -> Vec<ShowMetadata> {
+        self.shows.values().collect()
+    }
+
+    /// The `limit` shows starting at `from_index`, at most `MAX_BATCH_SIZE`
+    pub fn get_shows(&self, from_index: u64, limit: u64) -> Vec<ShowMetadata> {
+        self.shows
+            .values()
+            .skip(from_index as usize)
+            .take(limit.min(MAX_BATCH_SIZE as u64) as usize)
+            .collect()
+    }
+
+    pub fn shows_count(&self) -> u64 {
+        self.shows.len()
+    }
+
+    /// Tickets currently minted, burned and returned ones are not counted
+    pub fn total_tickets_minted(&self) -> u64 {
+        self.tickets.len()
+    }
+
+    /// Tickets checked in at least once
+    pub fn total_tickets_checked(&self) -> u64 {
+        self.tickets_checked
+    }
+
+    /// Shows tagged with `category` among the `limit` shows starting at `from_index`.
+    /// At most `MAX_BATCH_SIZE` shows are scanned per call.
+    pub fn get_shows_by_category(
+        &self,
+        category: String,
+        from_index: u64,
+        limit: u64,
+    ) -> Vec<ShowMetadata> {
+        let shows = self.shows.values_as_vector();
+        let end = std::cmp::min(from_index + limit.min(MAX_BATCH_SIZE as u64), shows.len());
+        (from_index..end)
+            .filter_map(|index| shows.get(index))
+            .filter(|show| show.published && show.categories.contains(&category))
+            .collect()
+    }
+
+    /// Published shows whose title contains `query`, ignoring case, among the `limit`
+    /// shows starting at `from_index`. At most `MAX_BATCH_SIZE` shows are scanned per call.
+    pub fn search_shows(&self, query: String, from_index: u64, limit: u64) -> Vec<ShowMetadata> {
+        let query = query.to_lowercase();
+        let shows = self.shows.values_as_vector();
+        let end = std::cmp::min(from_index + limit.min(MAX_BATCH_SIZE as u64), shows.len());
+        (from_index..end)
+            .filter_map(|index| shows.get(index))
+            .filter(|show| {
+                show.published
+                    && show
+                        .show_title
+                        .as_ref()
+                        .is_some_and(|title| title.to_lowercase().contains(&query))
+            })
+            .collect()
+    }
+
+    pub fn show_metadata(&self, show_id: String) -> Option<ShowMetadata> {
+        self.shows.get(&show_id)
+    }
+
+    pub fn ticket_metadata(&self, token_id: TokenId) -> Option<TicketMetadata> {
+        let mut ticket = self.tickets.get(&token_id)?;
+        ticket.show = self.shows.get(&ticket.show_id);
+        Some(ticket)
+    }
+
+    /// Token, ticket and show data needed to render a ticket card in one call
+    pub fn nft_token_render(&self, token_id: TokenId) -> Option<RenderPayload> {
+        let token = self.token_of(&token_id)?;
+        let ticket = self.tickets.get(&token_id)?;
+        let show = self.shows.get(&ticket.show_id);
+        Some(RenderPayload {
+            token,
+            show_title: show.as_ref().and_then(|show| show.show_title.clone()),
+            show_description: show.as_ref().and_then(|show| show.show_description.clone()),
+            show_banner: show.as_ref().and_then(|show| show.show_banner.clone()),
+            show_time: show.as_ref().map(|show| show.show_time),
+            ticket,
+        })
+    }
+
+    /// Token ids of a show, cheaper than fetching full tokens
+    pub fn token_ids_for_show(&self, show_id: String, from_index: u64, limit: u64) -> Vec<TokenId> {
+        let token_ids = match self.tokens_per_show.get(&show_id) {
+            Some(token_ids) => token_ids,
+            None => return vec![],
+        };
+        let limit = std::cmp::min(limit, MAX_BATCH_SIZE as u64);
+        let end = std::cmp::min(from_index + limit, token_ids.len());
+        (from_index..end)
+            .filter_map(|index| token_ids.as_vector().get(index))
+            .collect()
+    }
+
+    /// Tickets of a show in the order of the per show index, at most `MAX_BATCH_SIZE`
+    pub fn get_tickets_by_show(
+        &self,
+        show_id: String,
+        from_index: u64,
+        limit: u64,
+    ) -> Vec<TicketMetadata> {
+        self.token_ids_for_show(show_id, from_index, limit)
+            .into_iter()
+            .filter_map(|token_id| self.ticket_metadata(token_id))
+            .collect()
+    }
+
+    /// `nft_tokens` of one show, paged like the enumeration standard, at most `MAX_BATCH_SIZE`
+    pub fn nft_tokens_for_show(
+        &self,
+        show_id: String,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Token> {
+        let from_index = from_index.map_or(0, |from_index| from_index.0 as u64);
+        let limit = limit.unwrap_or(MAX_BATCH_SIZE as u64);
+        self.token_ids_for_show(show_id, from_index, limit)
+            .into_iter()
+            .filter_map(|token_id| self.token_of(&token_id))
+            .collect()
+    }
+
+    pub fn tickets_sold_for_show(&self, show_id: String) -> u32 {
+        self.shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"))
+            .ticket_infos
+            .values()
+            .map(|info| info.sold)
+            .sum()
+    }
+
+    /// Metadata for each requested token id, `None` for unknown ids
+    pub fn tickets_metadata(&self, token_ids: Vec<TokenId>) -> Vec<Option<TicketMetadata>> {
+        assert!(
+            token_ids.len() <= MAX_BATCH_SIZE,
+            "Too many token ids, max {}",
+            MAX_BATCH_SIZE
+        );
+        token_ids
+            .into_iter()
+            .map(|token_id| self.ticket_metadata(token_id))
+            .collect()
+    }
+
+    pub fn get_tickets_by_owner(&self, owner: AccountId) -> Vec<TicketMetadata> {
+        self.owner_token_ids(&owner)
+            .into_iter()
+            .filter_map(|token_id: TokenId| self.ticket_metadata(token_id))
+            .collect()
+    }
+
+    /// The `limit` tickets of `owner` starting at `from_index`, at most `MAX_BATCH_SIZE`
+    pub fn get_tickets_by_owner_paged(
+        &self,
+        owner: AccountId,
+        from_index: u64,
+        limit: u64,
+    ) -> Vec<TicketMetadata> {
+        let token_ids = match self
+            .tokens
+            .tokens_per_owner
+            .as_ref()
+            .and_then(|tokens_per_owner| tokens_per_owner.get(&owner))
+        {
+            Some(token_ids) => token_ids,
+            None => return Vec::new(),
+        };
+        token_ids
+            .iter()
+            .skip(from_index as usize)
+            .take(limit.min(MAX_BATCH_SIZE as u64) as usize)
+            .filter_map(|token_id: TokenId| self.ticket_metadata(token_id))
+            .collect()
+    }
+
+    pub fn tickets_count_by_owner(&self, owner: AccountId) -> u64 {
+        self.tokens
+            .tokens_per_owner
+            .as_ref()
+            .and_then(|tokens_per_owner| tokens_per_owner.get(&owner))
+            .map_or(0, |token_ids| token_ids.len())
+    }
+
+    /// Tickets held and used by `account_id` and the shows they are for, the show ids in
+    /// the order of the owner index and at most `MAX_BATCH_SIZE` of them
+    pub fn account_summary(&self, account_id: AccountId) -> AccountSummary {
+        let mut summary = AccountSummary {
+            tickets_held: 0,
+            tickets_used: 0,
+            show_ids: Vec::new(),
+        };
+        for ticket in self
+            .owner_token_ids(&account_id)
+            .iter()
+            .filter_map(|token_id| self.tickets.get(token_id))
+        {
+            summary.tickets_held += 1;
+            if ticket.is_used {
+                summary.tickets_used += 1;
+            }
+            let new_show = !summary.show_ids.contains(&ticket.show_id);
+            if new_show && summary.show_ids.len() < MAX_BATCH_SIZE {
+                summary.show_ids.push(ticket.show_id);
+            }
+        }
+        summary
+    }
+
+    /// Tickets of `owner` that belong to one of `show_ids`
+    pub fn get_tickets_by_owner_for_shows(
+        &self,
+        owner: AccountId,
+        show_ids: Vec<String>,
+    ) -> Vec<TicketMetadata> {
+        let show_ids: HashSet<String> = show_ids.into_iter().collect();
+        self.owner_token_ids(&owner)
+            .into_iter()
+            .filter_map(|token_id: TokenId| self.tickets.get(&token_id))
+            .filter(|ticket| show_ids.contains(&ticket.show_id))
+            .map(|mut ticket| {
+                ticket.show = self.shows.get(&ticket.show_id);
+                ticket
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    fn assert_owner(&self) {
+        assert!(!self.is_ownerless(), "Ownership renounced, the contract has no owner");
+        assert!(
+            env::predecessor_account_id() == self.owner_id,
+            "Caller {} is not owner: {}",
+            env::predecessor_account_id(),
+            self.owner_id
+        );
+    }
+
+    fn mint_ticket(
+        &mut self,
+        show_id: String,
+        ticket_type: String,
+        buyer: AccountId,
+        price: Balance,
+        deposit: Balance,
+        seat: Option<String>,
+    ) -> Promise {
+        let serial = self.take_serials(&show_id, &ticket_type, 1);
+        let ticket_id = format!("{}.{}.{}", show_id, ticket_type, serial);
+        self.add_pending_mint(&show_id, &ticket_type);
+        let key = (show_id.clone(), buyer.clone());
+        let purchases = self.purchases_per_account.get(&key).unwrap_or(0);
+        self.purchases_per_account.insert(&key, &(purchases + 1));
+        log!(
+            "{}",
+            format!(
+                "Buy new ticket: show id: {}, ticket type: {}, ticket id: {}, price: {} YoctoNear",
+                show_id,
+                ticket_type,
+                ticket_id,
+                price
+            )
+        );
+        ex_self::nft_private_mint(
+            ticket_id,
+            ValidAccountId::try_from(buyer.clone()).unwrap(),
+            seat.clone(),
+            Some(U128(price)),
+            &env::current_account_id(),
+            self.mint_fee,
+            self.prepare_gas,
+        )
+        .then(ex_self::check_mint(
+            buyer,
+            show_id,
+            ticket_type,
+            deposit,
+            price,
+            self.mint_fee,
+            seat,
+            &env::current_account_id(),
+            0,
+            CHECK_MINT_GAS,
+        ))
+    }
+
+    /// `nft_token` by reference, the NFT core one consumes the contract
+    fn token_of(&self, token_id: &TokenId) -> Option<Token> {
+        Some(Token {
+            token_id: token_id.clone(),
+            owner_id: self.tokens.owner_by_id.get(token_id)?,
+            metadata: self
+                .tokens
+                .token_metadata_by_id
+                .as_ref()
+                .and_then(|by_id| by_id.get(token_id)),
+            approved_account_ids: self
+                .tokens
+                .approvals_by_id
+                .as_ref()
+                .map(|by_id| by_id.get(token_id).unwrap_or_default()),
+        })
+    }
+
+    fn mint_ticket_token(&mut self, ticket: TicketMetadata, receiver_id: ValidAccountId) -> Token {
+        let token_id = ticket.ticket_id.clone();
+        self.tickets.insert(&token_id, &ticket);
+        self.add_show_token(&ticket.show_id, &token_id);
+        let show = self.shows.get(&ticket.show_id);
+        let template = show
+            .as_ref()
+            .and_then(|show| show.ticket_infos.get(&ticket.ticket_type))
+            .and_then(|info| info.metadata_template.clone());
+        let fill =
+            |value: Option<String>| value.map(|value| value.replace("{ticket_id}", &token_id));
+        let (reference, extra) = match template {
+            Some(template) => (fill(template.reference), fill(template.extra)),
+            None => (None, None),
+        };
+        let media = match show.as_ref() {
+            Some(ShowMetadata {
+                base_uri: Some(base_uri),
+                ..
+            }) => Some(format!("{}/{}", base_uri.trim_end_matches('/'), token_id)),
+            Some(show) => show.media.clone(),
+            None => None,
+        }
+        .or_else(|| self.metadata.get().and_then(|metadata| metadata.icon))
+        .unwrap_or_else(|| DEFAULT_TICKET_MEDIA.to_string());
+        let title = format!(
+            "{} — {} #{}",
+            show.as_ref()
+                .and_then(|show| show.show_title.clone())
+                .unwrap_or_else(|| ticket.show_id.clone()),
+            ticket.ticket_type,
+            token_id.rsplit('.').next().unwrap_or_default()
+        );
+        let description = show
+            .and_then(|show| show.show_description)
+            .unwrap_or_else(|| "B-Event ticket".to_string());
+        let token = self.tokens.mint(
+            token_id,
+            receiver_id,
+            Some(TokenMetadata {
+                title: Some(title), // ex. "Arch Nemesis: Mail Carrier" or "Parcel #5055"
+                description: Some(description), // free-form description
+                media: Some(media), // URL to associated media, preferably to decentralized, content-addressed storage
+                media_hash: None, // Base64-encoded sha256 hash of content referenced by the `media` field. Required if `media` is included.
+                copies: Some(1), // number of copies of this set of metadata in existence when token was minted.
+                issued_at: Some(env::block_timestamp().to_string()), // ISO 8601 datetime when token was issued or minted
+                expires_at: None,     // ISO 8601 datetime when token expires
+                starts_at: None,      // ISO 8601 datetime when token starts being valid
+                updated_at: None,     // ISO 8601 datetime when token was last updated
+                extra, // anything extra the NFT wants to store on-chain. Can be stringified JSON.
+                reference, // URL to an off-chain JSON file with more info.
+                reference_hash: None, // Base64-encoded sha256 hash of JSON from reference field. Required if `reference` is included.
+            }),
+        );
+        TicketEvent::NftMint {
+            owner_id: token.owner_id.clone(),
+            token_ids: vec![token.token_id.clone()],
+        }
+        .emit();
+        token
+    }
+
+    fn add_show_token(&mut self, show_id: &str, token_id: &TokenId) {
+        let show_id = show_id.to_string();
+        let mut token_ids = self.tokens_per_show.get(&show_id).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::ShowTokens {
+                show_hash: env::sha256(show_id.as_bytes()),
+            })
+        });
+        token_ids.insert(token_id);
+        self.tokens_per_show.insert(&show_id, &token_ids);
+    }
+
+    fn remove_show_token(&mut self, show_id: &str, token_id: &TokenId) {
+        let show_id = show_id.to_string();
+        if let Some(mut token_ids) = self.tokens_per_show.get(&show_id) {
+            token_ids.remove(token_id);
+            if token_ids.is_empty() {
+                self.tokens_per_show.remove(&show_id);
+            } else {
+                self.tokens_per_show.insert(&show_id, &token_ids);
+            }
+        }
+    }
+
+    /// Remove a token from the NFT core and its extensions
+    fn burn_token(&mut self, token_id: &TokenId) -> AccountId {
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .remove(token_id)
+            .unwrap_or_else(|| env::panic(b"Token not found"));
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            token_metadata_by_id.remove(token_id);
+        }
+        if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
+            let mut token_ids = tokens_per_owner.get(&owner_id).unwrap();
+            token_ids.remove(token_id);
+            if token_ids.is_empty() {
+                tokens_per_owner.remove(&owner_id);
+            } else {
+                tokens_per_owner.insert(&owner_id, &token_ids);
+            }
+        }
+        if let Some(approvals_by_id) = &mut self.tokens.approvals_by_id {
+            approvals_by_id.remove(token_id);
+        }
+        if let Some(next_approval_id_by_id) = &mut self.tokens.next_approval_id_by_id {
+            next_approval_id_by_id.remove(token_id);
+        }
+        owner_id
+    }
+
+    /// Token ids of `owner`, empty when the contract has no per owner index
+    fn owner_token_ids(&self, owner: &AccountId) -> Vec<TokenId> {
+        self.tokens
+            .tokens_per_owner
+            .as_ref()
+            .and_then(|tokens_per_owner| tokens_per_owner.get(owner))
+            .map(|token_ids| token_ids.to_vec())
+            .unwrap_or_default()
+    }
+
+    fn assert_on_sale(&self, show: &ShowMetadata, ticket_type: &str, amount: u32) {
+        self.assert_not_paused();
+        assert!(
+            show.ticket_infos.contains_key(ticket_type),
+            "ticket type {} does not exist for show {}",
+            ticket_type,
+            show.show_id
+        );
+        assert!(!show.is_cancelled, "This show is cancelled");
+        assert!(show.published, "This show is not published");
+        let (selling_start_time, selling_end_time) = Self::selling_window(show, ticket_type);
+        assert!(
+            env::block_timestamp() > selling_start_time,
+            "This show has not started selling tickets yet {}",
+            selling_start_time
+        );
+        assert!(
+            env::block_timestamp() < selling_end_time,
+            "This show has ended ticket sales {}",
+            selling_end_time
+        );
+        let remaining = self.remaining_of(show, ticket_type);
+        assert!(remaining > 0, "All tickets are sold out");
+        assert!(amount <= remaining, "Only {} tickets left", remaining);
+    }
+
+    fn check_in(&mut self, ticket_id: String) {
+        self.assert_not_paused();
+        if let Err(message) = self.try_check_in(ticket_id) {
+            env::panic(message.as_bytes());
+        }
+    }
+
+    /// `check_in` reporting why a ticket can not be checked in instead of panicking
+    fn try_check_in(&mut self, ticket_id: String) -> Result<(), String> {
+        let mut ticket = self
+            .tickets
+            .get(&ticket_id)
+            .ok_or_else(|| "ticket id does not exist!".to_string())?;
+        if let Some(show) = self.shows.get(&ticket.show_id) {
+            if show.is_cancelled {
+                return Err("This show is cancelled".to_string());
+            }
+            if env::block_timestamp() > Self::expiry_of(&show) {
+                return Err("ticket expired".to_string());
+            }
+        }
+        let max_entries = self.max_entries_of(&ticket);
+        // a second scan of a single entry ticket is a double entry attempt, not a limit
+        if max_entries == 1 && ticket.is_used {
+            return Err(format!("Ticket {} already used", ticket_id));
+        }
+        if ticket.entry_count >= max_entries {
+            return Err(format!("Ticket {} has reached its entry limit {}", ticket_id, max_entries));
+        }
+        if ticket.entry_count == 0 {
+            self.tickets_checked += 1;
+        }
+        ticket.entry_count += 1;
+        ticket.is_used = true;
+        self.tickets.insert(&ticket_id, &ticket);
+        // a used ticket can not be resold
+        self.resale_listings.remove(&ticket_id);
+        log!("{}", format!("Ticket {} is checked", ticket_id));
+        TicketEvent::TicketCheck {
+            ticket_id,
+            show_id: ticket.show_id,
+        }
+        .emit();
+        Ok(())
+    }
+
+    /// Resale price cap of a ticket type, by its own markup or else the show's multiplier
+    fn resale_cap_of(&self, show: &ShowMetadata, ticket_type: &str) -> Option<Balance> {
+        let info = show
+            .ticket_infos
+            .get(ticket_type)
+            .unwrap_or_else(|| env::panic(b"This ticket is not exist"));
+        let cap_bps = match info.max_resale_markup_bps {
+            Some(markup_bps) => BPS_DENOMINATOR as u32 + markup_bps as u32,
+            None => show.max_resale_multiplier?,
+        };
+        let face_value = match info.usd_cents {
+            None => info.price.0,
+            Some(usd_cents) => {
+                usd_cents as Balance
+                    * self.cached_usd_rate().unwrap_or_else(|| {
+                        env::panic(b"NEAR/USD rate is not available, try again later")
+                    })
+            }
+        };
+        Some(face_value * cap_bps as Balance / BPS_DENOMINATOR as Balance)
+    }
+
+    fn assert_resellable(ticket: &TicketMetadata, show: &ShowMetadata) {
+        assert!(!ticket.is_used, "Ticket {} is used", ticket.ticket_id);
+        assert!(!show.is_cancelled, "Show {} is cancelled", show.show_id);
+        assert!(
+            env::block_timestamp() <= Self::expiry_of(show),
+            "Ticket {} expired",
+            ticket.ticket_id
+        );
+    }
+
+    /// When tickets of a show stop being valid, the end of the event or else of its sales
+    fn expiry_of(show: &ShowMetadata) -> Timestamp {
+        show.event_end_time.unwrap_or(show.selling_end_time)
+    }
+
+    /// Check-ins allowed for a ticket, `None` (or anything below 1) keeps the single-entry rule
+    fn max_entries_of(&self, ticket: &TicketMetadata) -> u32 {
+        self.shows
+            .get(&ticket.show_id)
+            .and_then(|show| show.ticket_infos.get(&ticket.ticket_type).cloned())
+            .and_then(|info| info.max_entries)
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    fn assert_not_paused(&self) {
+        assert!(!self.paused, "contract is paused");
+    }
+
+    fn assert_transferable(&self, token_id: &TokenId) {
+        if let Some(show) = self
+            .tickets
+            .get(token_id)
+            .and_then(|ticket| self.shows.get(&ticket.show_id))
+        {
+            assert!(!show.soulbound, "Tickets of show {} can not be transferred", show.show_id);
+            assert!(
+                show.transfer_lock_time
+                    .is_none_or(|lock_time| env::block_timestamp() < lock_time),
+                "Tickets of show {} can not be transferred since {}",
+                show.show_id,
+                show.transfer_lock_time.unwrap_or_default()
+            );
+        }
+    }
+
+    fn assert_presale_access(&self, show: &ShowMetadata, buyer: &AccountId) {
+        if show
+            .presale_end_time
+            .is_some_and(|presale_end_time| env::block_timestamp() < presale_end_time)
+        {
+            assert!(
+                self.allowlists
+                    .get(&show.show_id)
+                    .is_some_and(|allowlist| allowlist.contains(buyer)),
+                "Only allowlisted accounts can buy tickets before {}",
+                show.presale_end_time.unwrap()
+            );
+        }
+    }
+
+    fn assert_account_limit(&self, show: &ShowMetadata, buyer: &AccountId, amount: u32) {
+        if let Some(max_per_account) = show.max_per_account {
+            let purchases = self
+                .purchases_per_account
+                .get(&(show.show_id.clone(), buyer.clone()))
+                .unwrap_or(0);
+            assert!(
+                purchases + amount <= max_per_account,
+                "An account can buy at most {} tickets of this show, you bought {}",
+                max_per_account,
+                purchases
+            );
+        }
+    }
+
+    /// Selling window of a ticket type, each bound falls back to the show's.
+    /// Shows created before per type windows store 0 for an unset bound.
+    fn selling_window(show: &ShowMetadata, ticket_type: &str) -> (Timestamp, Timestamp) {
+        let info = show
+            .ticket_infos
+            .get(ticket_type)
+            .unwrap_or_else(|| env::panic(b"Ticket type not exist"));
+        (
+            info.selling_start_time
+                .filter(|time| *time != 0)
+                .unwrap_or(show.selling_start_time),
+            info.selling_end_time
+                .filter(|time| *time != 0)
+                .unwrap_or(show.selling_end_time),
+        )
+    }
+
+    /// Tickets of a type that can still be bought, excluding reserved blocks
+    fn remaining_of(&self, show: &ShowMetadata, ticket_type: &str) -> u32 {
+        let info = show
+            .ticket_infos
+            .get(ticket_type)
+            .unwrap_or_else(|| env::panic(b"Ticket type not exist"));
+        let remaining = info.supply.saturating_sub(self.taken_of(&show.show_id, info));
+        match &info.pool {
+            Some(pool) => remaining.min(self.pool_remaining_of(show, pool)),
+            None => remaining,
+        }
+    }
+
+    fn visible_remaining_of(&self, show: &ShowMetadata, ticket_type: &str) -> u32 {
+        let remaining = self.remaining_of(show, ticket_type);
+        if show.hide_exact_remaining {
+            Self::remaining_range(remaining).0
+        } else {
+            remaining
+        }
+    }
+
+    /// Lowest count and label of the range shown instead of an exact remaining count
+    fn remaining_range(remaining: u32) -> (u32, &'static str) {
+        match remaining {
+            0 => (0, "sold out"),
+            1..=9 => (1, "<10"),
+            10..=50 => (10, "10-50"),
+            51..=100 => (51, "50-100"),
+            _ => (101, ">100"),
+        }
+    }
+
+    /// Tickets of a type that are sold, reserved for a block or being minted
+    fn taken_of(&self, show_id: &str, info: &TicketInfo) -> u32 {
+        info.sold
+            + self.reserved_tickets(show_id, &info.ticket_type)
+            + self.pending_mints_of(show_id, &info.ticket_type)
+    }
+
+    fn pending_mints_of(&self, show_id: &str, ticket_type: &str) -> u32 {
+        self.pending_mints
+            .get(&Self::ticket_type_key(show_id, ticket_type))
+            .unwrap_or(0)
+    }
+
+    /// Hold a ticket for a scheduled mint so concurrent purchases can not oversell.
+    /// `nft_private_mint` turns it into a sale, the callbacks release it if the mint failed.
+    fn add_pending_mint(&mut self, show_id: &str, ticket_type: &str) {
+        let key = Self::ticket_type_key(show_id, ticket_type);
+        let pending = self.pending_mints.get(&key).unwrap_or(0);
+        self.pending_mints.insert(&key, &(pending + 1));
+    }
+
+    fn release_pending_mint(&mut self, show_id: &str, ticket_type: &str) {
+        let key = Self::ticket_type_key(show_id, ticket_type);
+        let pending = self.pending_mints.get(&key).unwrap_or(0);
+        if pending > 1 {
+            self.pending_mints.insert(&key, &(pending - 1));
+        } else {
+            self.pending_mints.remove(&key);
+        }
+    }
+
+    /// First step of `merge_shows`: move everything but the tickets from the source show
+    /// to the target and remove the source
+    fn start_show_merge(&mut self, source_id: &str, target_id: &str) {
+        let source = self
+            .shows
+            .get(&source_id.to_string())
+            .unwrap_or_else(|| env::panic(b"Source show not exist"));
+        let mut target = self
+            .shows
+            .get(&target_id.to_string())
+            .unwrap_or_else(|| env::panic(b"Target show not exist"));
+        for (ticket_type, info) in source.ticket_infos {
+            assert!(
+                self.reserved_tickets(source_id, &ticket_type) == 0
+                    && self.pending_mints_of(source_id, &ticket_type) == 0,
+                "Tickets of {} are still being minted, finish its block allocations first",
+                ticket_type
+            );
+            if let Some(pool) = &info.pool {
+                assert!(
+                    source.capacity_pools.contains_key(pool)
+                        && target.capacity_pools.contains_key(pool),
+                    "Capacity pool {} does not exist in both shows",
+                    pool
+                );
+            }
+            match target.ticket_infos.get_mut(&ticket_type) {
+                Some(existing) => {
+                    assert!(
+                        existing.price == info.price
+                            && existing.selling_start_time == info.selling_start_time
+                            && existing.selling_end_time == info.selling_end_time
+                            && existing.max_entries == info.max_entries
+                            && existing.usd_cents == info.usd_cents
+                            && existing.pool == info.pool
+                            && existing.ft_price == info.ft_price
+                            && existing.metadata_template == info.metadata_template
+                            && existing.max_resale_markup_bps == info.max_resale_markup_bps,
+                        "Ticket type {} is configured differently in both shows",
+                        ticket_type
+                    );
+                    existing.supply += info.supply;
+                    existing.sold += info.sold;
+                }
+                None => {
+                    target.ticket_infos.insert(ticket_type, info);
+                }
+            }
+        }
+        for (pool, capacity) in source.capacity_pools {
+            if let Some(existing) = target.capacity_pools.get_mut(&pool) {
+                *existing += capacity;
+            }
+        }
+        let source_ft_revenue = self.ft_revenue.get(&source_id.to_string()).unwrap_or(0);
+        assert!(
+            source_ft_revenue == 0 || source.ft_token_id == target.ft_token_id,
+            "Withdraw the token revenue of show {} first, the shows sell in different tokens",
+            source_id
+        );
+        self.shows.insert(&target_id.to_string(), &target);
+        self.shows.remove(&source_id.to_string());
+
+        for revenue in [&mut self.revenue, &mut self.gross_revenue, &mut self.ft_revenue] {
+            if let Some(amount) = revenue.remove(&source_id.to_string()) {
+                let total = revenue.get(&target_id.to_string()).unwrap_or(0) + amount;
+                revenue.insert(&target_id.to_string(), &total);
+            }
+        }
+
+        let seats: Vec<String> = self
+            .claimed_seats
+            .iter()
+            .filter(|(show_id, _)| show_id == source_id)
+            .map(|(_, seat)| seat)
+            .collect();
+        for seat in seats {
+            self.claimed_seats.remove(&(source_id.to_string(), seat.clone()));
+            assert!(
+                self.claimed_seats.insert(&(target_id.to_string(), seat.clone())),
+                "Seat {} is taken in both shows",
+                seat
+            );
+        }
+
+        let purchases: Vec<(AccountId, u32)> = self
+            .purchases_per_account
+            .iter()
+            .filter(|((show_id, _), _)| show_id == source_id)
+            .map(|((_, account_id), count)| (account_id, count))
+            .collect();
+        for (account_id, count) in purchases {
+            self.purchases_per_account.remove(&(source_id.to_string(), account_id.clone()));
+            let key = (target_id.to_string(), account_id);
+            let total = self.purchases_per_account.get(&key).unwrap_or(0) + count;
+            self.purchases_per_account.insert(&key, &total);
+        }
+        log!("Merge show {} into {}", source_id, target_id);
+    }
+
+    /// Capacity of a pool left after the sold and reserved tickets of all its types
+    fn pool_remaining_of(&self, show: &ShowMetadata, pool: &str) -> u32 {
+        let capacity = *show
+            .capacity_pools
+            .get(pool)
+            .unwrap_or_else(|| env::panic(b"Capacity pool not exist"));
+        let used: u32 = show
+            .ticket_infos
+            .values()
+            .filter(|info| info.pool.as_deref() == Some(pool))
+            .map(|info| self.taken_of(&show.show_id, info))
+            .sum();
+        capacity.saturating_sub(used)
+    }
+
+    /// Emit `waitlist_notified` for the next `count` waiting accounts, at most
+    /// `MAX_BATCH_SIZE`, and take them off the waitlist
+    fn notify_waitlist(&mut self, show_id: &str, ticket_type: &str, count: u32) {
+        let key = Self::ticket_type_key(show_id, ticket_type);
+        let mut waitlist = match self.waitlists.get(&key) {
+            Some(waitlist) => waitlist,
+            None => return,
+        };
+        let end = std::cmp::min(
+            waitlist.next + count.min(MAX_BATCH_SIZE as u32) as u64,
+            waitlist.accounts.len(),
+        );
+        for index in waitlist.next..end {
+            let account_id = waitlist.accounts.get(index).unwrap();
+            self.waitlisted.remove(&(key.clone(), account_id.clone()));
+            TicketEvent::WaitlistNotified {
+                account_id,
+                show_id: show_id.to_string(),
+                ticket_type: ticket_type.to_string(),
+            }
+            .emit();
+        }
+        waitlist.next = end;
+        self.waitlists.insert(&key, &waitlist);
+    }
+
+    fn ticket_type_key(show_id: &str, ticket_type: &str) -> String {
+        format!("{}.{}", show_id, ticket_type)
+    }
+
+    fn reserved_tickets(&self, show_id: &str, ticket_type: &str) -> u32 {
+        self.reserved_tickets
+            .get(&Self::ticket_type_key(show_id, ticket_type))
+            .unwrap_or(0)
+    }
+
+    /// Reserve `count` consecutive serials of a ticket type and return the first one.
+    /// Serials are taken when a mint is scheduled so concurrent purchases never share one.
+    fn take_serials(&mut self, show_id: &str, ticket_type: &str, count: u32) -> u32 {
+        let key = Self::ticket_type_key(show_id, ticket_type);
+        let serial = self.next_serials.get(&key).unwrap_or_else(|| {
+            self.shows
+                .get(&show_id.to_string())
+                .and_then(|show| show.ticket_infos.get(ticket_type).map(|info| info.sold))
+                .unwrap_or(0)
+        });
+        self.next_serials.insert(&key, &(serial + count));
+        serial
+    }
+
+    /// Count a minted ticket as sold, the supply and the pool of its type must have room
+    fn record_sale(&mut self, show_id: &str, ticket_type: &str) {
+        let mut show = self.shows.get(&show_id.to_string()).unwrap();
+        assert!(
+            self.remaining_of(&show, ticket_type) > 0,
+            "All tickets of {} are sold out",
+            ticket_type
+        );
+        let mut ticket_info = show.ticket_infos.get(ticket_type).unwrap().clone();
+        ticket_info.sold += 1;
+        show.ticket_infos.insert(ticket_type.to_string(), ticket_info);
+        self.shows.insert(&show_id.to_string(), &show);
+    }
+
+    fn schedule_block_mints(&mut self, block_id: u64) -> Promise {
+        let mut block = self
+            .block_allocations
+            .get(block_id)
+            .unwrap_or_else(|| env::panic(b"Block allocation not exist"));
+        let retries = std::cmp::min(block.failed_serials.len() as u32, MAX_BLOCK_MINTS_PER_CALL);
+        let fresh = std::cmp::min(block.count - block.scheduled, MAX_BLOCK_MINTS_PER_CALL - retries);
+        let batch = retries + fresh;
+        assert!(batch > 0, "All tickets of block {} are minted", block_id);
+        let deposit = self.mint_fee + TICKET_STORAGE_DEPOSIT;
+        assert!(
+            env::attached_deposit() >= deposit * batch as Balance,
+            "Please deposit {} to mint {} tickets",
+            deposit * batch as Balance,
+            batch
+        );
+        assert!(
+            env::prepaid_gas() - env::used_gas()
+                >= (self.prepare_gas + BLOCK_MINT_CALLBACK_GAS) * batch as Gas + SCHEDULE_GAS,
+            "Not enough gas to mint {} tickets, please attach more gas",
+            batch
+        );
+        let surplus = env::attached_deposit() - deposit * batch as Balance;
+        if surplus > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(surplus);
+        }
+        let first = block.first_serial + block.scheduled;
+        let mut serials: Vec<u32> = block.failed_serials.drain(..retries as usize).collect();
+        serials.extend(first..first + fresh);
+        block.scheduled += fresh;
+        self.block_allocations.replace(block_id, &block);
+        let payer = env::predecessor_account_id();
+        let mut promise: Option<Promise> = None;
+        for serial in serials {
+            let mint = ex_self::nft_block_mint(
+                block_id,
+                serial,
+                &env::current_account_id(),
+                self.mint_fee,
+                self.prepare_gas,
+            )
+            .then(ex_self::on_block_mint(
+                block_id,
+                serial,
+                payer.clone(),
+                U128(self.mint_fee),
+                &env::current_account_id(),
+                0,
+                BLOCK_MINT_CALLBACK_GAS,
+            ));
+            promise = Some(match promise {
+                Some(promise) => promise.and(mint),
+                None => mint,
+            });
+        }
+        promise.unwrap()
+    }
+
+    /// Balance that is neither locked for storage nor owed to pending refunds
+    fn liquid_balance(&self) -> Balance {
+        let storage_cost = env::storage_usage() as Balance * env::storage_byte_cost();
+        env::account_balance().saturating_sub(storage_cost + self.total_pending_refunds)
+    }
+
+    /// The show locks its revenue and its event, or else its sales, did not end yet, or its
+    /// tickets can still be returned
+    fn revenue_locked(&self, show_id: &str) -> bool {
+        self.shows.get(&show_id.to_string()).is_some_and(|show| {
+            (show.proceeds_locked_until_event && env::block_timestamp() <= Self::expiry_of(&show))
+                || Self::returns_until(&show).is_some_and(|until| env::block_timestamp() <= until)
+        })
+    }
+
+    /// End of the refund window of the last ticket the show can sell
+    fn returns_until(show: &ShowMetadata) -> Option<Timestamp> {
+        show.refund_window_ns.map(|window| show.selling_end_time.saturating_add(window))
+    }
+
+    /// The show is cancelled and `cancel_show_refund_batch` has tickets left to refund
+    fn cancel_refunds_pending(&self, show_id: &str) -> bool {
+        let show_id = show_id.to_string();
+        self.shows.get(&show_id).is_some_and(|show| show.is_cancelled)
+            && self.tokens_per_show.get(&show_id).is_some_and(|token_ids| {
+                self.cancel_refund_progress.get(&show_id).unwrap_or(0) < token_ids.len()
+            })
+    }
+
+    fn deduct_revenue(&mut self, show_id: &str, amount: Balance) {
+        let show_id = show_id.to_string();
+        let revenue = self.revenue.get(&show_id).unwrap_or(0).saturating_sub(amount);
+        self.revenue.insert(&show_id, &revenue);
+    }
+
+    /// Refund from the contract balance. When the balance can not cover it the amount is
+    /// recorded as owed and the account claims it with `claim_refund` once topped up.
+    fn refund(&mut self, account_id: AccountId, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+        if self.liquid_balance() >= amount {
+            Promise::new(account_id).transfer(amount);
+        } else {
+            let owed = self.pending_refunds.get(&account_id).unwrap_or(0) + amount;
+            self.pending_refunds.insert(&account_id, &owed);
+            self.total_pending_refunds += amount;
+            log!(
+                "Not enough funds, record pending refund: {} YoctoNear to {}",
+                amount,
+                account_id
+            );
+        }
+    }
+
+    fn required_deposit(&self, price: Balance) -> Balance {
+        price + self.mint_fee + TICKET_STORAGE_DEPOSIT
+    }
+
+    fn cached_usd_rate(&self) -> Option<Balance> {
+        self.usd_rate
+            .as_ref()
+            .filter(|rate| env::block_timestamp() <= rate.updated_at + USD_RATE_TTL)
+            .map(|rate| rate.rate)
+    }
+
+    /// Read the oracle answer of the previous promise, caching it when it is within bounds
+    fn read_usd_rate_result(&mut self) -> Option<Balance> {
+        let oracle = self.price_oracle.as_ref()?;
+        let rate = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<U128>(&value).ok()?.0
+            }
+            _ => return None,
+        };
+        if rate < oracle.min_rate.0 || rate > oracle.max_rate.0 {
+            log!("Rejected NEAR/USD rate {} from the price oracle", rate);
+            return None;
+        }
+        self.usd_rate = Some(UsdRate {
+            rate,
+            updated_at: env::block_timestamp(),
+        });
+        Some(rate)
+    }
+
+    fn set_show_published(&mut self, show_id: String, published: bool) {
+        self.assert_owner();
+        let mut show = self
+            .shows
+            .get(&show_id)
+            .unwrap_or_else(|| env::panic(b"This show not exist"));
+        show.published = published;
+        self.shows.insert(&show_id, &show);
+        log!("Show {} published: {}", show_id, published);
+    }
+
+    fn create_show(&mut self, input: NewShowInput) {
+        Self::assert_valid(self.show_config_errors(&input));
+        assert!(
+            self.show_merges.get(&input.show_id).is_none(),
+            "Show {} is being merged into another show",
+            input.show_id
+        );
+        // show_config_errors accepts an existing show only when it was created the same way,
+        // so retrying a creation is a no-op
+        if self.shows.get(&input.show_id).is_some() {
+            log!("Show {} already exists", input.show_id);
+            return;
+        }
+        let creation_hash = Self::creation_hash(&input);
+        let mut show = Self::build_show(input);
+        show.creation_hash = Some(creation_hash);
+        self.shows.insert(&show.show_id, &show);
+    }
+
+    fn build_show(input: NewShowInput) -> ShowMetadata {
+        let mut ticket_infos = HashMap::new();
+        for i in 0..input.ticket_types.len() {
+            let window = input
+                .ticket_selling_windows
+                .as_ref()
+                .map_or((None, None), |windows| windows[i]);
+            let ticket_info = TicketInfo {
+                supply: input.tickets_supply[i],            // required
+                ticket_type: input.ticket_types[i].clone(), // required,
+                price: input.ticket_prices[i],
+                sold: 0u32,
+                selling_start_time: window.0,
+                selling_end_time: window.1,
+                max_entries: None,
+                usd_cents: input.ticket_usd_prices.as_ref().and_then(|prices| prices[i]),
+                ft_price: None,
+                pool: input.ticket_pools.as_ref().and_then(|pools| pools[i].clone()),
+                metadata_template: input
+                    .metadata_templates
+                    .as_ref()
+                    .and_then(|templates| templates[i].clone()),
+                max_resale_markup_bps: None,
+            };
+            ticket_infos.insert(input.ticket_types[i].clone(), ticket_info);
+        }
+        ShowMetadata {
+            show_id: input.show_id,
+            show_title: input.show_title,
+            show_description: input.show_description,
+            ticket_infos,
+            show_time: input.show_time,
+            show_banner: input.show_banner,
+            selling_start_time: input.selling_start_time,
+            selling_end_time: input.selling_end_time,
+            categories: input.categories.unwrap_or_default(),
+            capacity_pools: input.capacity_pools.unwrap_or_default(),
+            royalty: input.royalty.unwrap_or_default(),
+            splits: input.splits.unwrap_or_default(),
+            event_start_time: input.event_start_time,
+            event_end_time: input.event_end_time,
+            published: false,
+            hide_exact_remaining: false,
+            collectible: None,
+            is_cancelled: false,
+            max_per_account: None,
+            presale_end_time: None,
+            ft_token_id: None,
+            soulbound: false,
+            transfer_lock_time: None,
+            max_resale_multiplier: None,
+            media: None,
+            base_uri: None,
+            refund_window_ns: None,
+            proceeds_locked_until_event: false,
+            creation_hash: None,
+        }
+    }
+
+    /// sha256 of the show `input` creates, the same for every retry of the creation
+    fn creation_hash(input: &NewShowInput) -> Vec<u8> {
+        env::sha256(&Self::build_show(input.clone()).try_to_vec().unwrap())
+    }
+
+    /// Validation of `create_new_show`, shared with `validate_show_config`
+    fn show_config_errors(&self, input: &NewShowInput) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if let Some(message) = Self::id_error(&input.show_id) {
+            errors.push(ValidationError::new("show_id", message));
+        }
+        errors.extend(self.show_details_errors(&input.show_title, &input.show_description));
+        if let Some(categories) = &input.categories {
+            errors.extend(Self::category_errors(categories));
+        }
+        if let Some(royalty) = &input.royalty {
+            errors.extend(Self::royalty_errors(royalty));
+        }
+        if let Some(splits) = &input.splits {
+            errors.extend(Self::split_errors(splits));
+        }
+        if input.selling_start_time >= input.selling_end_time {
+            errors.push(ValidationError::new(
+                "selling_end_time",
+                "Selling end time must be after the start time".to_string(),
+            ));
+        }
+
+        if input.ticket_types.is_empty() {
+            errors.push(ValidationError::new(
+                "ticket_types",
+                "At least one ticket type is required".to_string(),
+            ));
+        }
+        if input.ticket_types.len() != input.tickets_supply.len()
+            || input.ticket_types.len() != input.ticket_prices.len()
+        {
+            errors.push(ValidationError::new(
+                "ticket_types",
+                "ticket_types, tickets_supply and ticket_prices must have the same length"
+                    .to_string(),
+            ));
+            return errors;
+        }
+        if let Some(usd_prices) = &input.ticket_usd_prices {
+            if usd_prices.len() != input.ticket_types.len() {
+                errors.push(ValidationError::new(
+                    "ticket_usd_prices",
+                    "ticket_usd_prices must have the same length as ticket_types".to_string(),
+                ));
+                return errors;
+            }
+            for (i, usd_cents) in usd_prices.iter().enumerate() {
+                if *usd_cents == Some(0) {
+                    errors.push(ValidationError::new(
+                        &format!("ticket_usd_prices[{}]", i),
+                        "USD price must be greater than 0 cents".to_string(),
+                    ));
+                }
+            }
+        }
+        let capacity_pools = input.capacity_pools.clone().unwrap_or_default();
+        for (pool, capacity) in capacity_pools.iter() {
+            let field = format!("capacity_pools.{}", pool);
+            if let Some(message) = Self::id_error(pool) {
+                errors.push(ValidationError::new(&field, message));
+            }
+            if *capacity == 0 {
+                errors.push(ValidationError::new(
+                    &field,
+                    "Pool capacity must be greater than 0".to_string(),
+                ));
+            }
+        }
+        if let Some(ticket_pools) = &input.ticket_pools {
+            if ticket_pools.len() != input.ticket_types.len() {
+                errors.push(ValidationError::new(
+                    "ticket_pools",
+                    "ticket_pools must have the same length as ticket_types".to_string(),
+                ));
+                return errors;
+            }
+            for (i, pool) in ticket_pools.iter().enumerate() {
+                if let Some(pool) = pool {
+                    if !capacity_pools.contains_key(pool) {
+                        errors.push(ValidationError::new(
+                            &format!("ticket_pools[{}]", i),
+                            format!("Capacity pool {} not exist", pool),
+                        ));
+                    }
+                }
+            }
+        }
+        if let (Some(start), Some(end)) = (input.event_start_time, input.event_end_time) {
+            if start >= end {
+                errors.push(ValidationError::new(
+                    "event_end_time",
+                    "Event end time must be after the start time".to_string(),
+                ));
+            }
+        }
+        if let Some(templates) = &input.metadata_templates {
+            if templates.len() != input.ticket_types.len() {
+                errors.push(ValidationError::new(
+                    "metadata_templates",
+                    "metadata_templates must have the same length as ticket_types".to_string(),
+                ));
+                return errors;
+            }
+        }
+        if let Some(windows) = &input.ticket_selling_windows {
+            if windows.len() != input.ticket_types.len() {
+                errors.push(ValidationError::new(
+                    "ticket_selling_windows",
+                    "ticket_selling_windows must have the same length as ticket_types".to_string(),
+                ));
+                return errors;
+            }
+            for (i, window) in windows.iter().enumerate() {
+                if let Some(message) = Self::window_error(window.0, window.1) {
+                    errors.push(ValidationError::new(
+                        &format!("ticket_selling_windows[{}]", i),
+                        message,
+                    ));
+                }
+            }
+        }
+        let mut seen = HashSet::new();
+        for (i, ticket_type) in input.ticket_types.iter().enumerate() {
+            if !seen.insert(ticket_type) {
+                errors.push(ValidationError::new(
+                    &format!("ticket_types[{}]", i),
+                    format!("Duplicate ticket type {}", ticket_type),
+                ));
+            }
+            let supply = input.tickets_supply[i];
+            let price = input.ticket_prices[i].0;
+            for (field, message) in Self::ticket_info_errors(ticket_type, supply, price) {
+                let field = match field {
+                    "ticket_type" => "ticket_types",
+                    "supply" => "tickets_supply",
+                    _ => "ticket_prices",
+                };
+                errors.push(ValidationError::new(&format!("{}[{}]", field, i), message));
+            }
+        }
+        if !errors.is_empty() {
+            return errors;
+        }
+
+        match self.shows.get(&input.show_id) {
+            Some(existing) => {
+                // later edits of the show do not change the hash of its creation
+                if existing.is_cancelled {
+                    errors.push(ValidationError::new(
+                        "show_id",
+                        format!("Show {} was cancelled", input.show_id),
+                    ));
+                } else if existing.creation_hash != Some(Self::creation_hash(input)) {
+                    errors.push(ValidationError::new("show_id", "This show exist".to_string()));
+                }
+            }
+            None => {
+                if let Some(max_shows) = self.max_shows {
+                    if self.shows.len() >= max_shows {
+                        errors.push(ValidationError::new(
+                            "show_id",
+                            format!("This contract can not have more than {} shows", max_shows),
+                        ));
+                    }
+                }
+            }
+        }
+        errors
+    }
+
+    /// Problems of one ticket type as (`TicketInfo` field, message)
+    fn ticket_info_errors(
+        ticket_type: &str,
+        supply: u32,
+        price: Balance,
+    ) -> Vec<(&'static str, String)> {
+        let mut errors = vec![];
+        // ticket ids are `{show_id}.{ticket_type}.{serial}`, like show ids types have no '.'
+        if let Some(message) = Self::id_error(ticket_type) {
+            errors.push(("ticket_type", message));
+        }
+        if supply == 0 {
+            errors.push(("supply", "Ticket supply must be greater than 0".to_string()));
+        }
+        if price > MAX_TICKET_PRICE {
+            errors.push((
+                "price",
+                format!("Ticket price must be at most {} yoctoNEAR", MAX_TICKET_PRICE),
+            ));
+        }
+        errors
+    }
+
+    /// Exact yoctoNEAR amount of the shortest decimal form of `near`, e.g. 1.1 is
+    /// 1_100_000_000_000_000_000_000_000. Digits below one yoctoNEAR are dropped.
+    fn near_to_yocto(near: f64) -> Option<Balance> {
+        if !near.is_finite() || near < 0.0 {
+            return None;
+        }
+        let decimal = near.to_string();
+        let (whole, fraction) = decimal.split_once('.').unwrap_or((&decimal, ""));
+        let fraction = format!("{:0<24}", fraction);
+        whole
+            .parse::<Balance>()
+            .ok()?
+            .checked_mul(ONE_NEAR)?
+            .checked_add(fraction[..24].parse().ok()?)
+    }
+
+    fn window_error(start: Option<Timestamp>, end: Option<Timestamp>) -> Option<String> {
+        match (start, end) {
+            (Some(start), Some(end)) if start >= end => {
+                Some("Selling end time must be after the start time".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    fn assert_pool_exists(show: &ShowMetadata, info: &TicketInfo) {
+        if let Some(pool) = &info.pool {
+            assert!(
+                show.capacity_pools.contains_key(pool),
+                "Capacity pool {} not exist",
+                pool
+            );
+        }
+    }
+
+    /// Show ids and ticket types end up in token ids, which are split on '.'
+    fn id_error(id: &str) -> Option<String> {
+        if id.is_empty() || id.len() > MAX_ID_LENGTH {
+            Some(format!("Must be 1 to {} bytes long", MAX_ID_LENGTH))
+        } else if id.contains('.') {
+            Some("Must not contain '.'".to_string())
+        } else {
+            None
+        }
+    }
+
+    fn royalty_errors(royalty: &HashMap<AccountId, u16>) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if royalty.len() > MAX_ROYALTY_ACCOUNTS {
+            errors.push(ValidationError::new(
+                "royalty",
+                format!("Too many royalty accounts, max {}", MAX_ROYALTY_ACCOUNTS),
+            ));
+        }
+        let total: u32 = royalty.values().map(|bps| *bps as u32).sum();
+        if total > BPS_DENOMINATOR as u32 {
+            errors.push(ValidationError::new(
+                "royalty",
+                format!("Royalties must not exceed {} basis points", BPS_DENOMINATOR),
+            ));
+        }
+        errors
+    }
+
+    fn split_errors(splits: &[(AccountId, u16)]) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if splits.is_empty() {
+            return errors;
+        }
+        if splits.len() > MAX_ROYALTY_ACCOUNTS {
+            errors.push(ValidationError::new(
+                "splits",
+                format!("Too many split accounts, max {}", MAX_ROYALTY_ACCOUNTS),
+            ));
+        }
+        let accounts: HashSet<&AccountId> =
+            splits.iter().map(|(account_id, _)| account_id).collect();
+        if accounts.len() < splits.len() {
+            errors.push(ValidationError::new("splits", "Duplicate split account".to_string()));
+        }
+        let total: u32 = splits.iter().map(|(_, bps)| *bps as u32).sum();
+        if total != BPS_DENOMINATOR as u32 {
+            errors.push(ValidationError::new(
+                "splits",
+                format!("Splits must add up to {} basis points", BPS_DENOMINATOR),
+            ));
+        }
+        errors
+    }
+
+    fn category_errors(categories: &[String]) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if categories.len() > MAX_CATEGORIES {
+            errors.push(ValidationError::new(
+                "categories",
+                format!("Too many categories, max {}", MAX_CATEGORIES),
+            ));
+        }
+        for (i, category) in categories.iter().enumerate() {
+            if category.is_empty() || category.len() > MAX_CATEGORY_LENGTH {
+                errors.push(ValidationError::new(
+                    &format!("categories[{}]", i),
+                    format!("Category must be 1 to {} bytes long", MAX_CATEGORY_LENGTH),
+                ));
+            }
+        }
+        errors
+    }
+
+    fn show_details_errors(
+        &self,
+        title: &Option<String>,
+        description: &Option<String>,
+    ) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if self.require_show_details {
+            if title.as_ref().is_none_or(|title| title.is_empty()) {
+                errors.push(ValidationError::new(
+                    "show_title",
+                    "Show title is required".to_string(),
+                ));
+            }
+            if description.as_ref().is_none_or(|description| description.is_empty()) {
+                errors.push(ValidationError::new(
+                    "show_description",
+                    "Show description is required".to_string(),
+                ));
+            }
+        }
+        if title.as_ref().map_or(0, |title| title.len()) > MAX_TITLE_LENGTH {
+            errors.push(ValidationError::new(
+                "show_title",
+                format!("Show title is too long, max {} bytes", MAX_TITLE_LENGTH),
+            ));
+        }
+        if description.as_ref().map_or(0, |description| description.len()) > MAX_DESCRIPTION_LENGTH {
+            errors.push(ValidationError::new(
+                "show_description",
+                format!("Show description is too long, max {} bytes", MAX_DESCRIPTION_LENGTH),
+            ));
+        }
+        errors
+    }
+
+    /// Panic with the first validation error, if any
+    fn assert_valid(errors: Vec<ValidationError>) {
+        if let Some(error) = errors.into_iter().next() {
+            env::panic(format!("{}: {}", error.field, error.message).as_bytes());
+        }
+    }
+}
+
+// The NFT core of `impl_non_fungible_token_core!`, with transfers guarded by the show rules
+#[near_bindgen]
+impl NonFungibleTokenCore for Contract {
+    #[payable]
+    fn nft_transfer(
+        &mut self,
+        receiver_id: ValidAccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+    ) {
+        self.assert_transferable(&token_id);
+        self.tokens.nft_transfer(receiver_id, token_id, approval_id, memo)
+    }
+
+    #[payable]
+    fn nft_transfer_call(
+        &mut self,
+        receiver_id: ValidAccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        self.assert_transferable(&token_id);
+        self.tokens.nft_transfer_call(receiver_id, token_id, approval_id, memo, msg)
+    }
+
+    fn nft_token(self, token_id: TokenId) -> Option<Token> {
+        self.tokens.nft_token(token_id)
+    }
+
+    fn mint(
+        &mut self,
+        token_id: TokenId,
+        token_owner_id: ValidAccountId,
+        token_metadata: Option<TokenMetadata>,
+    ) -> Token {
+        self.tokens.mint(token_id, token_owner_id, token_metadata)
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenResolver for Contract {
+    #[private]
+    fn nft_resolve_transfer(
+        &mut self,
+        previous_owner_id: AccountId,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approved_account_ids: Option<HashMap<AccountId, u64>>,
+    ) -> bool {
+        self.tokens.nft_resolve_transfer(
+            previous_owner_id,
+            receiver_id,
+            token_id,
+            approved_account_ids,
+        )
+    }
+}
+
+near_contract_standards::impl_non_fungible_token_approval!(Contract, tokens);
+near_contract_standards::impl_non_fungible_token_enumeration!(Contract, tokens);
+
+#[near_bindgen]
+impl NonFungibleTokenMetadataProvider for Contract {
+    /// NEP-177 view of `ticket_contract_metadata`
+    fn nft_metadata(&self) -> NFTContractMetadata {
+        let metadata = self.metadata.get().unwrap();
+        NFTContractMetadata {
+            spec: metadata.spec,
+            name: metadata.name,
+            symbol: metadata.symbol,
+            icon: metadata.icon,
+            base_uri: metadata.base_uri,
+            reference: None,
+            reference_hash: None,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn ticket_contract_metadata(&self) -> TicketContractMetadata {
+        self.metadata.get().unwrap()
+    }
+
+    /// Factory that deployed this contract, if any, and when
+    pub fn deploy_info(&self) -> DeployInfo {
+        DeployInfo {
+            factory_id: self.factory_id.clone(),
+            deployed_at: self.deployed_at,
+        }
+    }
+
+    /// Version of the deployed code, `git_hash` is set when built with `GIT_HASH` in the env
+    pub fn version(&self) -> VersionInfo {
+        VersionInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_hash: option_env!("GIT_HASH")
+                .filter(|hash| !hash.is_empty())
+                .map(|hash| hash.to_string()),
+            spec: self.metadata.get().unwrap().spec,
+        }
+    }
+
+    /// Version of the deployed code, as the factory that deployed or upgraded it named it,
+    /// else the package version. Compare with the factory's `embedded_contract_version` to
+    /// know whether an upgrade is available.
+    pub fn contract_version(&self) -> String {
+        self.code_version
+            .clone()
+            .unwrap_or_else(|| env!("CARGO_PKG_VERSION").to_string())
+    }
+
+    /// Delete this account and send its balance to `beneficiary`, called by the factory
+    /// that deployed this contract on behalf of `requester`, who must be the current owner.
+    /// Only an empty contract is deleted: no tickets, no revenue left to withdraw and no
+    /// refunds owed.
+    pub fn delete_self(&mut self, beneficiary: AccountId, requester: AccountId) -> Promise {
+        assert!(
+            self.factory_id == Some(env::predecessor_account_id()),
+            "Only the factory can delete this contract"
+        );
+        assert!(
+            !self.is_ownerless() && requester == self.owner_id,
+            "Caller {} is not owner: {}",
+            requester,
+            self.owner_id
+        );
+        assert!(self.tickets.is_empty(), "Contract has tickets, burn them first");
+        assert!(
+            self.revenue.values().all(|revenue| revenue == 0)
+                && self.ft_revenue.values().all(|revenue| revenue == 0),
+            "Contract has revenue, withdraw it first"
+        );
+        assert!(self.total_pending_refunds == 0, "Contract owes refunds, pay them first");
+        log!("Delete ticket contract, balance goes to {}", beneficiary);
+        Promise::new(env::current_account_id()).delete_account(beneficiary)
+    }
+
+    /// Convert the state of the first version, run by `upgrade` right after the new code
+    /// is deployed. A contract with tickets stays paused until `migrate_tickets` converted
+    /// all of them.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let old: ContractV0 =
+            env::state_read().unwrap_or_else(|| env::panic(b"No state to migrate"));
+        old.into()
+    }
+
+    /// Convert the next `limit` tickets left by `migrate`, at most `MAX_BATCH_SIZE`.
+    /// Returns true, and unpauses the contract, once all tickets are converted.
+    pub fn migrate_tickets(&mut self, limit: u64) -> bool {
+        self.assert_owner();
+        let from = match self.ticket_migration {
+            Some(from) => from,
+            None => return true,
+        };
+        let to = std::cmp::min(from + limit.min(MAX_BATCH_SIZE as u64), self.tickets.len());
+        self.convert_tickets(from, to);
+        if to == self.tickets.len() {
+            self.ticket_migration = None;
+            self.paused = false;
+            log!("All {} tickets are migrated", to);
+            true
+        } else {
+            self.ticket_migration = Some(to);
+            false
+        }
+    }
+
+    /// Deploy new code of `code_version`, called by the factory that deployed this contract.
+    /// With `migrate` the new code's `migrate` runs right after to convert the state.
+    pub fn upgrade(&mut self, code: Base64VecU8, migrate: bool, code_version: String) -> Promise {
+        assert!(
+            self.factory_id == Some(env::predecessor_account_id()),
+            "Only the factory can upgrade this contract"
+        );
+        self.code_version = Some(code_version);
+        let promise = Promise::new(env::current_account_id()).deploy_contract(code.into());
+        if migrate {
+            promise.function_call(b"migrate".to_vec(), b"{}".to_vec(), 0, MIGRATE_GAS)
+        } else {
+            promise
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TicketContractMetadata {
+    pub spec: String,   // required, essentially a version like "nft-1.0.0"
+    pub name: String,   // required, ex. "Mosaics"
+    pub symbol: String, // required, ex. "MOSIAC"
+    pub description: Option<String>,
+    pub icon: Option<String>, // fallback media of minted tickets
+    #[serde(default)]
+    pub base_uri: Option<String>, // gateway for relative `media` and `reference` links
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RenderPayload {
+    pub token: Token,
+    pub ticket: TicketMetadata, // `show` is left empty, see the show fields below
+    pub show_title: Option<String>,
+    pub show_description: Option<String>,
+    pub show_banner: Option<String>,
+    pub show_time: Option<Timestamp>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DeployInfo {
+    pub factory_id: Option<AccountId>,
+    pub deployed_at: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VersionInfo {
+    pub version: String,
+    pub git_hash: Option<String>,
+    pub spec: String,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TicketMetadata {
+    pub ticket_id: String,   // required
+    pub show_id: String,     // required,
+    pub ticket_type: String, // required,
+    pub is_used: bool,       // required,
+    pub entry_count: u32,    // number of successful check-ins
+    pub unchecked_reason: Option<String>, // set when a check-in was reverted by the owner
+    pub checkin_refunded: bool,
+    issued_at: Timestamp,
+    pub show: Option<ShowMetadata>, // required
+    pub reissued_from: Option<TokenId>, // ticket this one replaces
+    pub collectible: bool,              // converted to a souvenir after the show
+    pub seat: Option<String>,           // reserved seat, None => general admission
+    pub price: Option<U128>,            // yoctoNEAR paid for it, None => not bought with NEAR
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TicketInfo {
+    pub supply: u32,         // required
+    pub ticket_type: String, // required,
+    pub price: U128, // face value in yoctoNEAR, fees are charged on top of it
+    pub sold: u32,
+    pub selling_start_time: Option<Timestamp>,
+    pub selling_end_time: Option<Timestamp>,
+    pub max_entries: Option<u32>, // check-ins allowed per ticket, None => single entry
+    pub usd_cents: Option<u64>, // when set the price is converted from USD at purchase time
+    pub pool: Option<String>,   // capacity pool shared with other types of the show
+    pub ft_price: Option<U128>, // price in the show's fungible token, None => not for sale in it
+    pub metadata_template: Option<MetadataTemplate>, // `reference` and `extra` of its tokens
+    #[serde(default)]
+    pub max_resale_markup_bps: Option<u16>, // resale cap above the face value, None => the show's
+}
+
+/// `reference` and `extra` of minted tokens, `{ticket_id}` is replaced by the token id
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MetadataTemplate {
+    pub reference: Option<String>, // ex. "https://example.com/tickets/{ticket_id}.json"
+    pub extra: Option<String>,     // ex. "{\"ticket\":\"{ticket_id}\",\"perks\":[\"lounge\"]}"
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(crate = "near_sdk::serde")]
 pub struct ShowMetadata {
     pub show_id: String, // required,
@@ -409,10 +4309,260 @@ pub struct ShowMetadata {
     // pub ticket_price_by_type: HashMap<String, Balance>,    // required, type ticket =>
     pub selling_start_time: Timestamp, // required
     pub selling_end_time: Timestamp,   // required
+    pub categories: Vec<String>,       // ex. "music", "sports"
+    pub capacity_pools: HashMap<String, u32>, // pool => capacity, ex. "vip" => 100
+    pub royalty: HashMap<AccountId, u16>, // account => basis points paid out of resales
+    pub splits: Vec<(AccountId, u16)>, // account => basis points of the revenue, empty => all to the owner
+    pub event_start_time: Option<Timestamp>, // the event itself, apart from the sales window
+    pub event_end_time: Option<Timestamp>,   // tickets expire then, None => at the end of sales
+    pub published: bool,               // drafts can not be bought and are hidden from discovery
+    pub hide_exact_remaining: bool,    // show remaining tickets as a range
+    pub collectible: Option<CollectibleMetadata>, // souvenir look of used tickets after the show
+    pub is_cancelled: bool,
+    pub max_per_account: Option<u32>, // tickets one account can buy, None => unlimited
+    pub presale_end_time: Option<Timestamp>, // only allowlisted accounts can buy before it
+    pub ft_token_id: Option<AccountId>, // fungible token accepted by `ft_on_transfer`
+    pub soulbound: bool,                // tickets can not be transferred
+    pub transfer_lock_time: Option<Timestamp>, // tickets can not be transferred from then on
+    pub max_resale_multiplier: Option<u32>, // resale price cap in basis points of the face value, None => no resale
+    pub media: Option<String>,    // image of the show's tickets
+    pub base_uri: Option<String>, // tickets link `{base_uri}/{token_id}` as media when set
+    pub refund_window_ns: Option<Timestamp>, // tickets can be returned this long after the purchase
+    pub proceeds_locked_until_event: bool, // revenue can be withdrawn once the event ended
+    #[serde(skip)]
+    pub creation_hash: Option<Vec<u8>>, // sha256 of the show as created, retries match it
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceOracle {
+    pub account_id: AccountId, // returns yoctoNEAR per US cent from `get_near_usd_rate`
+    pub min_rate: U128,
+    pub max_rate: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct UsdRate {
+    pub rate: Balance, // yoctoNEAR per US cent
+    pub updated_at: Timestamp,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectibleMetadata {
+    pub title: String,
+    pub media: String, // URL of the commemorative artwork
+}
+
+/// Parameters of `create_new_show`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NewShowInput {
+    pub show_id: String,
+    pub show_title: Option<String>,
+    pub show_description: Option<String>,
+    pub show_time: Timestamp,
+    pub show_banner: Option<String>,
+    pub ticket_types: Vec<String>,
+    pub tickets_supply: Vec<u32>,
+    pub ticket_prices: Vec<U128>, // yoctoNEAR
+    pub selling_start_time: Timestamp,
+    pub selling_end_time: Timestamp,
+    pub categories: Option<Vec<String>>,
+    pub capacity_pools: Option<HashMap<String, u32>>,
+    pub ticket_pools: Option<Vec<Option<String>>>,
+    pub ticket_selling_windows: Option<Vec<(Option<Timestamp>, Option<Timestamp>)>>,
+    pub royalty: Option<HashMap<AccountId, u16>>,
+    pub splits: Option<Vec<(AccountId, u16)>>,
+    pub metadata_templates: Option<Vec<Option<MetadataTemplate>>>,
+    pub event_start_time: Option<Timestamp>,
+    pub event_end_time: Option<Timestamp>,
+    pub ticket_usd_prices: Option<Vec<Option<u64>>>, // US cents
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ValidationError {
+    pub field: String, // ex. "ticket_prices[1]"
+    pub message: String,
+}
+
+impl ValidationError {
+    fn new(field: &str, message: String) -> Self {
+        Self {
+            field: field.to_string(),
+            message,
+        }
+    }
+}
+
+/// Accounts waiting for a ticket type, the ones before `next` were notified already
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Waitlist {
+    pub accounts: Vector<AccountId>,
+    pub next: u64,
+}
+
+/// Tickets with consecutive serials minted to one account by `allocate_block`
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BlockAllocation {
+    pub show_id: String,
+    pub ticket_type: String,
+    pub receiver: AccountId,
+    pub first_serial: u32,
+    pub count: u32,
+    pub scheduled: u32, // mints scheduled so far
+    pub minted: u32,    // mints that succeeded
+    pub allocated_at: Timestamp,
+    pub failed_serials: Vec<u32>, // serials of failed mints, scheduled again first
+}
+
+/// Progress of a `merge_shows` call that spans several transactions
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ShowMerge {
+    pub target_id: String,
+    pub cursor: u64, // tickets rebound so far
+}
+
+/// `msg` of an `ft_transfer_call` buying a ticket
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtPurchase {
+    pub show_id: String,
+    pub ticket_type: String,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ResaleListing {
+    pub seller: AccountId, // the listing is void once the ticket changed hands
+    pub price: U128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Payout {
+    pub payout: HashMap<AccountId, U128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountSummary {
+    pub tickets_held: u64,
+    pub tickets_used: u64,
+    pub show_ids: Vec<String>, // shows the account holds tickets for
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintedTicket {
+    pub token: Token,
+    pub storage_usage: U64, // bytes the mint added
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceBreakdown {
+    pub ticket_price: U128,
+    pub mint_fee: U128,
+    pub storage_deposit: U128, // the most storage is charged, the unused part is refunded
+    pub total: U128, // deposit required by `buy_ticket`
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum TicketStatus {
+    Unknown,
+    Valid,
+    Used,
+    Expired,
+    ShowCancelled,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum SaleState {
+    NotStarted,
+    Active,
+    Ended,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleTiming {
+    pub state: SaleState,
+    pub seconds_until_start: Option<u64>, // None once the sale has started
+    pub seconds_until_end: Option<u64>,   // None once the sale has ended
+}
+
+/// Revenue of a show by whether `withdraw_revenue` can pay it out now
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RevenueStatus {
+    pub locked: U128,
+    pub withdrawable: U128,
+}
+
+// the generated call builders take the account, deposit and gas on top of the arguments
+#[allow(clippy::too_many_arguments)]
+mod self_calls {
+    use super::*;
+
+    #[ext_contract(ex_self)]
+    trait TTicketContract {
+        fn nft_private_mint(
+            &mut self,
+            token_id: TokenId,
+            receiver_id: ValidAccountId,
+            seat: Option<String>,
+            price: Option<U128>,
+        ) -> MintedTicket;
+        fn check_mint(
+            &mut self,
+            buyer: AccountId,
+            show_id: String,
+            ticket_type: String,
+            deposit: Balance,
+            price: Balance,
+            mint_fee: Balance,
+            seat: Option<String>,
+        ) -> Option<TokenId>;
+        fn nft_block_mint(&mut self, block_id: u64, serial: u32) -> Token;
+        fn on_block_mint(
+            &mut self,
+            block_id: u64,
+            serial: u32,
+            payer: AccountId,
+            mint_fee: U128,
+        ) -> bool;
+        fn nft_reissue_mint(&mut self, old_token_id: TokenId, new_owner: ValidAccountId) -> Token;
+        fn on_usd_rate_for_purchase(
+            &mut self,
+            buyer: AccountId,
+            show_id: String,
+            ticket_type: String,
+            deposit: U128,
+        ) -> Promise;
+        fn on_ft_mint(
+            &mut self,
+            buyer: AccountId,
+            show_id: String,
+            ticket_type: String,
+            amount: U128,
+            price: U128,
+        ) -> U128;
+        fn on_ft_revenue_withdrawn(&mut self, show_id: String, amount: U128);
+    }
+}
+use self_calls::ex_self;
+
+#[ext_contract(ext_fungible_token)]
+trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
 }
 
-#[ext_contract(ex_self)]
-trait TTicketContract {
-    fn nft_private_mint(&mut self, token_id: TokenId, receiver_id: ValidAccountId) -> Token;
-    fn check_mint(&self, buyer: AccountId, price: Balance);
+#[ext_contract(ext_price_oracle)]
+trait PriceOracleContract {
+    fn get_near_usd_rate(&self) -> U128;
 }
@@ -0,0 +1,239 @@
+//! Layout of the first deployed version of the contract, read by `Contract::migrate` after
+//! an upgrade. The top level struct, the contract metadata and the shows are converted by
+//! `migrate`, the tickets can be too many for one call and are converted in batches by
+//! `migrate_tickets`.
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV0 {
+    pub(crate) owner_id: AccountId,
+    pub(crate) tokens: NonFungibleToken,
+    pub(crate) metadata: LazyOption<TicketContractMetadataV0>,
+    pub(crate) shows: UnorderedMap<String, ShowMetadataV0>,
+    pub(crate) tickets: UnorderedMap<TokenId, TicketMetadataV0>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TicketContractMetadataV0 {
+    pub(crate) spec: String,
+    pub(crate) name: String,
+    pub(crate) symbol: String,
+    pub(crate) description: Option<String>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TicketMetadataV0 {
+    pub(crate) ticket_id: String,
+    pub(crate) show_id: String,
+    pub(crate) ticket_type: String,
+    pub(crate) is_used: bool,
+    pub(crate) issued_at: Timestamp,
+    pub(crate) show: Option<ShowMetadataV0>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TicketInfoV0 {
+    pub(crate) supply: u32,
+    pub(crate) ticket_type: String,
+    pub(crate) price: Balance,
+    pub(crate) sold: u32,
+    pub(crate) selling_start_time: Option<Timestamp>,
+    pub(crate) selling_end_time: Option<Timestamp>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ShowMetadataV0 {
+    pub(crate) show_id: String,
+    pub(crate) show_title: Option<String>,
+    pub(crate) show_description: Option<String>,
+    pub(crate) ticket_infos: HashMap<String, TicketInfoV0>,
+    pub(crate) show_time: Timestamp,
+    pub(crate) show_banner: Option<String>,
+    pub(crate) selling_start_time: Timestamp,
+    pub(crate) selling_end_time: Timestamp,
+}
+
+impl From<TicketContractMetadataV0> for TicketContractMetadata {
+    fn from(old: TicketContractMetadataV0) -> Self {
+        Self {
+            spec: old.spec,
+            name: old.name,
+            symbol: old.symbol,
+            description: old.description,
+            icon: None,
+            base_uri: None,
+        }
+    }
+}
+
+impl From<TicketInfoV0> for TicketInfo {
+    fn from(old: TicketInfoV0) -> Self {
+        Self {
+            supply: old.supply,
+            ticket_type: old.ticket_type,
+            price: U128(old.price),
+            sold: old.sold,
+            selling_start_time: old.selling_start_time,
+            selling_end_time: old.selling_end_time,
+            max_entries: None,
+            usd_cents: None,
+            pool: None,
+            ft_price: None,
+            metadata_template: None,
+            max_resale_markup_bps: None,
+        }
+    }
+}
+
+impl From<ShowMetadataV0> for ShowMetadata {
+    fn from(old: ShowMetadataV0) -> Self {
+        Self {
+            show_id: old.show_id,
+            show_title: old.show_title,
+            show_description: old.show_description,
+            ticket_infos: old
+                .ticket_infos
+                .into_iter()
+                .map(|(ticket_type, info)| (ticket_type, info.into()))
+                .collect(),
+            show_time: old.show_time,
+            show_banner: old.show_banner,
+            selling_start_time: old.selling_start_time,
+            selling_end_time: old.selling_end_time,
+            categories: vec![],
+            capacity_pools: HashMap::new(),
+            royalty: HashMap::new(),
+            splits: vec![],
+            event_start_time: None,
+            event_end_time: None,
+            // shows of the first version were on sale as soon as they were created
+            published: true,
+            hide_exact_remaining: false,
+            collectible: None,
+            is_cancelled: false,
+            max_per_account: None,
+            presale_end_time: None,
+            ft_token_id: None,
+            soulbound: false,
+            transfer_lock_time: None,
+            max_resale_multiplier: None,
+            media: None,
+            base_uri: None,
+            refund_window_ns: None,
+            proceeds_locked_until_event: false,
+            // shows of the first version can not be created again
+            creation_hash: None,
+        }
+    }
+}
+
+impl From<TicketMetadataV0> for TicketMetadata {
+    fn from(old: TicketMetadataV0) -> Self {
+        Self {
+            ticket_id: old.ticket_id,
+            show_id: old.show_id,
+            ticket_type: old.ticket_type,
+            is_used: old.is_used,
+            entry_count: old.is_used as u32,
+            unchecked_reason: None,
+            checkin_refunded: false,
+            issued_at: old.issued_at,
+            show: old.show.map(ShowMetadata::from),
+            reissued_from: None,
+            collectible: false,
+            seat: None,
+            // the price paid was not recorded
+            price: None,
+        }
+    }
+}
+
+impl From<ContractV0> for Contract {
+    fn from(old: ContractV0) -> Self {
+        let metadata = old.metadata.get().map(TicketContractMetadata::from);
+        let mut shows = retype::<_, UnorderedMap<String, ShowMetadata>>(&old.shows);
+        convert_values(&mut shows, 0..old.shows.len(), |old: ShowMetadataV0| old.into());
+        let tickets = retype(&old.tickets);
+        Self {
+            owner_id: old.owner_id,
+            tokens: old.tokens,
+            metadata: LazyOption::new(StorageKey::Metadata, metadata.as_ref()),
+            shows,
+            tickets,
+            show_merges: UnorderedMap::new(StorageKey::ShowMerge),
+            checkin_refund_bps: BPS_DENOMINATOR,
+            require_show_details: false,
+            price_oracle: None,
+            usd_rate: None,
+            next_serials: LookupMap::new(StorageKey::NextSerials),
+            pending_refunds: UnorderedMap::new(StorageKey::PendingRefunds),
+            total_pending_refunds: 0,
+            max_shows: None,
+            reserved_tickets: LookupMap::new(StorageKey::ReservedTickets),
+            block_allocations: Vector::new(StorageKey::BlockAllocations),
+            // contracts of the first version were all created by a factory as its sub accounts
+            factory_id: env::current_account_id()
+                .split_once('.')
+                .map(|(_, parent_id)| parent_id.to_string()),
+            deployed_at: env::block_timestamp(),
+            code_version: None,
+            tokens_per_show: LookupMap::new(StorageKey::TokensPerShow),
+            cancel_refund_progress: LookupMap::new(StorageKey::CancelRefundProgress),
+            purchases_per_account: UnorderedMap::new(StorageKey::PurchasesPerAccount),
+            allowlists: LookupMap::new(StorageKey::Allowlists),
+            pending_owner: None,
+            scanners: LookupMap::new(StorageKey::Scanners),
+            revenue: UnorderedMap::new(StorageKey::Revenue),
+            mint_fee: DEFAULT_MINT_FEE,
+            prepare_gas: DEFAULT_PREPARE_GAS,
+            ft_revenue: UnorderedMap::new(StorageKey::FtRevenue),
+            // nothing that reads tickets can run before `migrate_tickets` converted them all
+            paused: !old.tickets.is_empty(),
+            claimed_seats: UnorderedSet::new(StorageKey::ClaimedSeats),
+            resale_listings: UnorderedMap::new(StorageKey::ResaleListings),
+            gross_revenue: UnorderedMap::new(StorageKey::GrossRevenue),
+            waitlists: LookupMap::new(StorageKey::Waitlists),
+            waitlisted: UnorderedSet::new(StorageKey::Waitlisted),
+            tickets_checked: 0,
+            pending_mints: LookupMap::new(StorageKey::PendingMints),
+            ticket_migration: Some(0).filter(|_| !old.tickets.is_empty()),
+        }
+    }
+}
+
+impl Contract {
+    /// Convert the tickets in `from..to` of `tickets` from the first layout, index them by
+    /// show and count the used ones
+    pub(crate) fn convert_tickets(&mut self, from: u64, to: u64) {
+        convert_values(&mut self.tickets, from..to, |old: TicketMetadataV0| old.into());
+        for index in from..to {
+            let ticket = self.tickets.values_as_vector().get(index).unwrap();
+            self.add_show_token(&ticket.show_id, &ticket.ticket_id);
+            if ticket.is_used {
+                self.tickets_checked += 1;
+            }
+        }
+    }
+}
+
+/// The same collection with another element type, collections only store their prefixes
+fn retype<T: BorshSerialize, U: BorshDeserialize>(collection: &T) -> U {
+    U::try_from_slice(&collection.try_to_vec().unwrap()).unwrap()
+}
+
+/// Rewrite the values at `indexes` of `map`, stored as `Old`, as `V`
+fn convert_values<K, V, Old>(
+    map: &mut UnorderedMap<K, V>,
+    indexes: std::ops::Range<u64>,
+    convert: impl Fn(Old) -> V,
+) where
+    K: BorshSerialize + BorshDeserialize,
+    V: BorshSerialize + BorshDeserialize,
+    Old: BorshDeserialize,
+{
+    for index in indexes {
+        let key = map.keys_as_vector().get_raw(index).unwrap();
+        let old = Old::try_from_slice(&map.values_as_vector().get_raw(index).unwrap()).unwrap();
+        map.insert_raw(&key, &convert(old).try_to_vec().unwrap());
+    }
+}
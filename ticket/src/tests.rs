@@ -0,0 +1,2234 @@
+use crate::migrate::*;
+use crate::*;
+use near_sdk::test_utils::{accounts, get_created_receipts, get_logs, VMContextBuilder};
+use near_sdk::{serde_json, testing_env, MockedBlockchain};
+
+// accounts(0) is the contract, accounts(1) its owner and the others buyers
+const NOW: Timestamp = 100;
+
+fn context(predecessor: ValidAccountId) -> VMContextBuilder {
+    let mut builder = VMContextBuilder::new();
+    builder
+        .current_account_id(accounts(0))
+        .signer_account_id(predecessor.clone())
+        .predecessor_account_id(predecessor)
+        .block_timestamp(NOW);
+    builder
+}
+
+fn setup() -> Contract {
+    testing_env!(context(accounts(1)).build());
+    Contract::new(
+        accounts(1).into(),
+        TicketContractMetadata {
+            spec: "nft-1.0.0".to_string(),
+            name: "Tickets".to_string(),
+            symbol: "TKT".to_string(),
+            description: None,
+            icon: None,
+            base_uri: Some("https://tickets.example".to_string()),
+        },
+        None,
+        None,
+        None,
+    )
+}
+
+/// A published show on sale until 1000 with 10 "vip" tickets of 1 NEAR
+fn show_input(show_id: &str) -> NewShowInput {
+    NewShowInput {
+        show_id: show_id.to_string(),
+        show_title: Some("Show".to_string()),
+        show_description: None,
+        show_time: 2000,
+        show_banner: None,
+        ticket_types: vec!["vip".to_string()],
+        tickets_supply: vec![10],
+        ticket_prices: vec![U128(ONE_NEAR)],
+        selling_start_time: 0,
+        selling_end_time: 1000,
+        categories: None,
+        capacity_pools: None,
+        ticket_pools: None,
+        ticket_selling_windows: None,
+        royalty: None,
+        splits: None,
+        metadata_templates: None,
+        event_start_time: None,
+        event_end_time: None,
+        ticket_usd_prices: None,
+    }
+}
+
+fn create(contract: &mut Contract, input: NewShowInput) {
+    testing_env!(context(accounts(1)).build());
+    let show_id = input.show_id.clone();
+    contract.create_new_show(
+        input.show_id,
+        input.show_title,
+        input.show_description,
+        input.show_time,
+        input.show_banner,
+        input.ticket_types,
+        input.tickets_supply,
+        input.ticket_prices,
+        input.selling_start_time,
+        input.selling_end_time,
+        input.categories,
+        input.capacity_pools,
+        input.ticket_pools,
+        input.ticket_selling_windows,
+        input.royalty,
+        input.splits,
+        input.metadata_templates,
+        input.event_start_time,
+        input.event_end_time,
+        input.ticket_usd_prices,
+    );
+    contract.publish_show(show_id);
+}
+
+fn price_of(contract: &Contract, show_id: &str, ticket_type: &str) -> Balance {
+    contract.show_metadata(show_id.to_string()).unwrap().ticket_infos[ticket_type].price.0
+}
+
+fn cost_of(contract: &Contract, show_id: &str, ticket_type: &str) -> Balance {
+    contract.required_deposit(price_of(contract, show_id, ticket_type))
+}
+
+/// Id of the ticket the last purchase of a type scheduled
+fn last_ticket_id(contract: &Contract, show_id: &str, ticket_type: &str) -> TokenId {
+    let next = contract.next_serials.get(&Contract::ticket_type_key(show_id, ticket_type));
+    format!("{}.{}.{}", show_id, ticket_type, next.unwrap() - 1)
+}
+
+/// The `nft_private_mint` of a purchase, as the contract calls it
+fn private_mint(contract: &mut Contract, token_id: &str, buyer: ValidAccountId) -> MintedTicket {
+    testing_env!(context(accounts(0)).attached_deposit(contract.mint_fee).build());
+    let parts: Vec<&str> = token_id.split('.').collect();
+    let price = price_of(contract, parts[0], parts[1]);
+    contract.nft_private_mint(token_id.to_string(), buyer, None, Some(U128(price)))
+}
+
+/// The `check_mint` callback of a purchase after its mint resolved to `minted`
+fn finish_mint(
+    contract: &mut Contract,
+    buyer: ValidAccountId,
+    token_id: &str,
+    deposit: Balance,
+    minted: Option<&MintedTicket>,
+) -> Option<TokenId> {
+    let result = match minted {
+        Some(minted) => PromiseResult::Successful(serde_json::to_vec(minted).unwrap()),
+        None => PromiseResult::Failed,
+    };
+    testing_env!(
+        context(accounts(0)).build(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        vec![result]
+    );
+    let parts: Vec<&str> = token_id.split('.').collect();
+    let price = price_of(contract, parts[0], parts[1]);
+    let mint_fee = contract.mint_fee;
+    contract.check_mint(
+        buyer.into(),
+        parts[0].to_string(),
+        parts[1].to_string(),
+        deposit,
+        price,
+        mint_fee,
+        None,
+    )
+}
+
+/// Buy a ticket with `deposit` and run the promise chain of the purchase
+fn buy_with(
+    contract: &mut Contract,
+    buyer: ValidAccountId,
+    show_id: &str,
+    ticket_type: &str,
+    deposit: Balance,
+) -> TokenId {
+    testing_env!(context(buyer.clone()).attached_deposit(deposit).build());
+    contract.buy_ticket(show_id.to_string(), ticket_type.to_string());
+    let token_id = last_ticket_id(contract, show_id, ticket_type);
+    let minted = private_mint(contract, &token_id, buyer.clone());
+    finish_mint(contract, buyer, &token_id, deposit, Some(&minted)).unwrap()
+}
+
+fn buy(
+    contract: &mut Contract,
+    buyer: ValidAccountId,
+    show_id: &str,
+    ticket_type: &str,
+) -> TokenId {
+    let deposit = cost_of(contract, show_id, ticket_type);
+    buy_with(contract, buyer, show_id, ticket_type, deposit)
+}
+
+/// (receiver, amount) of the transfers scheduled since the last `testing_env!`
+fn transfers() -> Vec<(AccountId, Balance)> {
+    get_created_receipts()
+        .iter()
+        .flat_map(|receipt| {
+            let receipt = format!("{:?}", receipt);
+            let receiver = receipt.split("receiver_id: \"").nth(1).unwrap();
+            let receiver = receiver.split('"').next().unwrap().to_string();
+            receipt
+                .split("Transfer(TransferAction { deposit: ")
+                .skip(1)
+                .map(|rest| (receiver.clone(), rest.split(' ').next().unwrap().parse().unwrap()))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn check(contract: &mut Contract, holder: ValidAccountId, ticket_id: &str) {
+    testing_env!(context(holder).attached_deposit(1).build());
+    contract.check_ticket(ticket_id.to_string());
+}
+
+#[test]
+fn deposit_is_price_mint_fee_and_storage_deposit() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let breakdown = contract.ticket_price_breakdown("show".to_string(), "vip".to_string());
+    assert_eq!(breakdown.ticket_price, U128(ONE_NEAR));
+    assert_eq!(breakdown.mint_fee, U128(DEFAULT_MINT_FEE));
+    assert_eq!(breakdown.storage_deposit, U128(TICKET_STORAGE_DEPOSIT));
+    assert_eq!(breakdown.total, U128(ONE_NEAR + DEFAULT_MINT_FEE + TICKET_STORAGE_DEPOSIT));
+}
+
+#[test]
+#[should_panic(expected = "Please deposit at least")]
+fn buying_with_only_the_price_fails() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    testing_env!(context(accounts(2)).attached_deposit(ONE_NEAR).build());
+    contract.buy_ticket("show".to_string(), "vip".to_string());
+}
+
+#[test]
+fn purchase_refunds_the_overpayment_and_unused_storage() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let cost = cost_of(&contract, "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(cost + ONE_NEAR).build());
+    contract.buy_ticket("show".to_string(), "vip".to_string());
+    let minted = private_mint(&mut contract, "show.vip.0", accounts(2));
+    let deposit = cost + ONE_NEAR;
+    let token_id = finish_mint(&mut contract, accounts(2), "show.vip.0", deposit, Some(&minted));
+    assert_eq!(token_id, Some("show.vip.0".to_string()));
+    let storage = minted.storage_usage.0 as Balance * env::storage_byte_cost();
+    let storage = storage.min(TICKET_STORAGE_DEPOSIT);
+    let refund = ONE_NEAR + TICKET_STORAGE_DEPOSIT - storage;
+    assert_eq!(transfers(), vec![(accounts(2).into(), refund)]);
+    assert_eq!(contract.get_revenue("show".to_string()), U128(ONE_NEAR));
+    assert_eq!(contract.show_revenue("show".to_string()), U128(ONE_NEAR + DEFAULT_MINT_FEE));
+}
+
+#[test]
+fn twice_the_price_returns_exactly_the_surplus() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let deposit = 2 * ONE_NEAR;
+    testing_env!(context(accounts(2)).attached_deposit(deposit).build());
+    contract.buy_ticket("show".to_string(), "vip".to_string());
+    let minted = private_mint(&mut contract, "show.vip.0", accounts(2));
+    finish_mint(&mut contract, accounts(2), "show.vip.0", deposit, Some(&minted));
+    let storage = minted.storage_usage.0 as Balance * env::storage_byte_cost();
+    let storage = storage.min(TICKET_STORAGE_DEPOSIT);
+    let surplus = deposit - ONE_NEAR - DEFAULT_MINT_FEE - storage;
+    assert_eq!(transfers(), vec![(accounts(2).into(), surplus)]);
+}
+
+#[test]
+fn failed_mint_refunds_the_deposit_and_releases_the_ticket() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let cost = cost_of(&contract, "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(cost).build());
+    contract.buy_ticket("show".to_string(), "vip".to_string());
+    assert_eq!(contract.remaining_tickets("show".to_string(), "vip".to_string()), 9);
+    assert_eq!(finish_mint(&mut contract, accounts(2), "show.vip.0", cost, None), None);
+    assert_eq!(transfers(), vec![(accounts(2).into(), cost)]);
+    assert_eq!(contract.remaining_tickets("show".to_string(), "vip".to_string()), 10);
+    assert_eq!(contract.purchases_by_account("show".to_string(), accounts(2).into()), 0);
+    assert_eq!(contract.get_revenue("show".to_string()), U128(0));
+}
+
+#[test]
+fn the_last_ticket_can_be_sold() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.tickets_supply = vec![3];
+    create(&mut contract, input);
+    for serial in 0..3 {
+        assert_eq!(buy(&mut contract, accounts(2), "show", "vip"), format!("show.vip.{}", serial));
+    }
+    assert!(contract.is_type_sold_out("show".to_string(), "vip".to_string()));
+}
+
+#[test]
+#[should_panic(expected = "All tickets are sold out")]
+fn no_ticket_is_sold_beyond_the_supply() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.tickets_supply = vec![1];
+    create(&mut contract, input);
+    buy(&mut contract, accounts(2), "show", "vip");
+    assert!(contract.is_type_sold_out("show".to_string(), "vip".to_string()));
+    buy(&mut contract, accounts(3), "show", "vip");
+}
+
+#[test]
+#[should_panic(expected = "All tickets are sold out")]
+fn a_supply_of_100_sells_exactly_100_tickets() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.tickets_supply = vec![100];
+    create(&mut contract, input);
+    for sold in 0..100 {
+        assert_eq!(contract.remaining_tickets("show".to_string(), "vip".to_string()), 100 - sold);
+        buy(&mut contract, accounts(2), "show", "vip");
+    }
+    assert_eq!(contract.tickets_sold_for_show("show".to_string()), 100);
+    buy(&mut contract, accounts(3), "show", "vip");
+}
+
+#[test]
+#[should_panic(expected = "All tickets are sold out")]
+fn pending_mints_count_against_the_supply() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.tickets_supply = vec![1];
+    create(&mut contract, input);
+    let cost = cost_of(&contract, "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(cost).build());
+    contract.buy_ticket("show".to_string(), "vip".to_string());
+    testing_env!(context(accounts(3)).attached_deposit(cost).build());
+    contract.buy_ticket("show".to_string(), "vip".to_string());
+}
+
+#[test]
+fn buy_tickets_mints_each_ticket_and_refunds_the_excess() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let cost = cost_of(&contract, "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(cost * 5 + 7).build());
+    contract.buy_tickets("show".to_string(), "vip".to_string(), 5);
+    assert_eq!(transfers()[0], (accounts(2).into(), 7));
+    for serial in 0..5 {
+        let token_id = format!("show.vip.{}", serial);
+        let minted = private_mint(&mut contract, &token_id, accounts(2));
+        assert_eq!(
+            finish_mint(&mut contract, accounts(2), &token_id, cost, Some(&minted)),
+            Some(token_id)
+        );
+    }
+    assert_eq!(contract.tickets_sold_for_show("show".to_string()), 5);
+    assert_eq!(contract.tickets_count_by_owner(accounts(2).into()), 5);
+}
+
+#[test]
+#[should_panic(expected = "Can buy 1 to 5 tickets at once")]
+fn buy_tickets_is_bounded() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    testing_env!(context(accounts(2)).attached_deposit(100 * ONE_NEAR).build());
+    contract.buy_tickets("show".to_string(), "vip".to_string(), 6);
+}
+
+#[test]
+#[should_panic(expected = "Only 2 tickets left")]
+fn buy_tickets_respects_the_remaining_supply() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.tickets_supply = vec![2];
+    create(&mut contract, input);
+    testing_env!(context(accounts(2)).attached_deposit(100 * ONE_NEAR).build());
+    contract.buy_tickets("show".to_string(), "vip".to_string(), 3);
+}
+
+#[test]
+fn mint_and_check_emit_nep297_events() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let cost = cost_of(&contract, "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(cost).build());
+    contract.buy_ticket("show".to_string(), "vip".to_string());
+    let minted = private_mint(&mut contract, "show.vip.0", accounts(2));
+    assert_eq!(
+        get_logs(),
+        vec![
+            r#"EVENT_JSON:{"data":[{"owner_id":"charlie","token_ids":["show.vip.0"]}],"event":"nft_mint","standard":"nep171","version":"1.0.0"}"#
+        ]
+    );
+    finish_mint(&mut contract, accounts(2), "show.vip.0", cost, Some(&minted));
+    assert_eq!(
+        get_logs(),
+        vec![format!(
+            r#"EVENT_JSON:{{"data":[{{"buyer":"charlie","issued_at":"{}","price":"{}","show_id":"show","ticket_type":"vip","token_id":"show.vip.0"}}],"event":"ticket_purchased","standard":"ticket","version":"1.0.0"}}"#,
+            NOW, ONE_NEAR
+        )]
+    );
+    check(&mut contract, accounts(2), "show.vip.0");
+    assert_eq!(
+        get_logs(),
+        vec![
+            "Ticket show.vip.0 is checked".to_string(),
+            r#"EVENT_JSON:{"data":[{"show_id":"show","ticket_id":"show.vip.0"}],"event":"ticket_check","standard":"ticket","version":"1.0.0"}"#.to_string()
+        ]
+    );
+}
+
+#[test]
+fn migrate_converts_the_first_layout() {
+    testing_env!(context(accounts(0)).build());
+    let mut shows: UnorderedMap<String, ShowMetadataV0> =
+        UnorderedMap::new(StorageKey::ShowMetadata);
+    let mut ticket_infos = HashMap::new();
+    ticket_infos.insert(
+        "vip".to_string(),
+        TicketInfoV0 {
+            supply: 10,
+            ticket_type: "vip".to_string(),
+            price: ONE_NEAR,
+            sold: 2,
+            selling_start_time: None,
+            selling_end_time: None,
+        },
+    );
+    let show = ShowMetadataV0 {
+        show_id: "show".to_string(),
+        show_title: Some("Show".to_string()),
+        show_description: None,
+        ticket_infos,
+        show_time: 100,
+        show_banner: None,
+        selling_start_time: 0,
+        selling_end_time: 200,
+    };
+    shows.insert(&show.show_id, &show);
+    let mut tickets: UnorderedMap<TokenId, TicketMetadataV0> =
+        UnorderedMap::new(StorageKey::TicketMetadata);
+    for (serial, is_used) in [(0, true), (1, false)] {
+        let ticket_id = format!("show.vip.{}", serial);
+        tickets.insert(
+            &ticket_id,
+            &TicketMetadataV0 {
+                ticket_id: ticket_id.clone(),
+                show_id: "show".to_string(),
+                ticket_type: "vip".to_string(),
+                is_used,
+                issued_at: 50,
+                show: None,
+            },
+        );
+    }
+    let old = ContractV0 {
+        owner_id: accounts(1).into(),
+        tokens: NonFungibleToken::new(
+            StorageKey::NonFungibleToken,
+            accounts(0),
+            Some(StorageKey::TokenMetadata),
+            Some(StorageKey::Enumeration),
+            Some(StorageKey::Approval),
+        ),
+        metadata: LazyOption::new(
+            StorageKey::Metadata,
+            Some(&TicketContractMetadataV0 {
+                spec: "nft-1.0.0".to_string(),
+                name: "Tickets".to_string(),
+                symbol: "TKT".to_string(),
+                description: None,
+            }),
+        ),
+        shows,
+        tickets,
+    };
+    env::state_write(&old);
+
+    let mut contract = Contract::migrate();
+    let metadata = contract.ticket_contract_metadata();
+    assert_eq!(metadata.name, "Tickets");
+    assert_eq!(metadata.icon, None);
+    let show = &contract.get_all_shows()[0];
+    assert_eq!(show.ticket_infos["vip"].price, U128(ONE_NEAR));
+    assert_eq!(show.ticket_infos["vip"].sold, 2);
+    assert!(show.published);
+    assert!(contract.is_paused());
+
+    testing_env!(context(accounts(1)).build());
+    assert!(!contract.migrate_tickets(1));
+    assert!(contract.migrate_tickets(1));
+    assert!(!contract.is_paused());
+    let ticket = contract.tickets.get(&"show.vip.0".to_string()).unwrap();
+    assert!(ticket.is_used);
+    assert_eq!(ticket.entry_count, 1);
+    assert_eq!(ticket.price, None);
+    assert_eq!(contract.tokens_per_show.get(&"show".to_string()).unwrap().len(), 2);
+    assert_eq!(contract.total_tickets_checked(), 1);
+}
+
+#[test]
+fn migrated_contracts_are_upgraded_by_their_factory() {
+    let context = |predecessor: &str| {
+        context(accounts(1))
+            .current_account_id("show.factory".try_into().unwrap())
+            .predecessor_account_id(predecessor.try_into().unwrap())
+            .build()
+    };
+    testing_env!(context("show.factory"));
+    let old = ContractV0 {
+        owner_id: accounts(1).into(),
+        tokens: NonFungibleToken::new(
+            StorageKey::NonFungibleToken,
+            "show.factory".try_into().unwrap(),
+            Some(StorageKey::TokenMetadata),
+            Some(StorageKey::Enumeration),
+            Some(StorageKey::Approval),
+        ),
+        metadata: LazyOption::new(StorageKey::Metadata, None),
+        shows: UnorderedMap::new(StorageKey::ShowMetadata),
+        tickets: UnorderedMap::new(StorageKey::TicketMetadata),
+    };
+    env::state_write(&old);
+
+    let mut contract = Contract::migrate();
+    assert_eq!(contract.deploy_info().factory_id, Some("factory".to_string()));
+    testing_env!(context("factory"));
+    contract.upgrade(Base64VecU8(vec![0]), true, "0.2.0".to_string());
+    let receipts = format!("{:?}", get_created_receipts());
+    assert!(receipts.contains("DeployContract"));
+    assert!(receipts.contains("FunctionCall"));
+    assert_eq!(contract.contract_version(), "0.2.0");
+}
+
+fn act_as(account: ValidAccountId) {
+    testing_env!(context(account).build());
+}
+
+fn act_at(account: ValidAccountId, timestamp: Timestamp) {
+    testing_env!(context(account).block_timestamp(timestamp).build());
+}
+
+#[test]
+fn shows_are_paged() {
+    let mut contract = setup();
+    for i in 0..5 {
+        create(&mut contract, show_input(&format!("show{}", i)));
+    }
+    assert_eq!(contract.shows_count(), 5);
+    let page: Vec<String> =
+        contract.get_shows(1, 2).into_iter().map(|show| show.show_id).collect();
+    assert_eq!(page, vec!["show1", "show2"]);
+    assert_eq!(contract.get_shows(4, 10).len(), 1);
+    assert!(contract.get_shows(5, 10).is_empty());
+    assert_eq!(contract.get_all_shows().len(), 5);
+}
+
+#[test]
+fn prices_are_json_strings() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.ticket_prices = vec![U128(u128::MAX / 2)];
+    let value = serde_json::to_value(Contract::build_show(input)).unwrap();
+    assert_eq!(value["ticket_infos"]["vip"]["price"], (u128::MAX / 2).to_string());
+    create(&mut contract, show_input("show"));
+    let value = serde_json::to_value(contract.show_metadata("show".to_string())).unwrap();
+    assert_eq!(value["ticket_infos"]["vip"]["price"], ONE_NEAR.to_string());
+}
+
+#[test]
+fn near_prices_are_converted_exactly() {
+    let mut contract = setup();
+    act_as(accounts(1));
+    let input = show_input("show");
+    contract.create_new_show_near_prices(
+        input.show_id,
+        None,
+        None,
+        input.show_time,
+        None,
+        vec!["vip".to_string(), "ga".to_string()],
+        vec![10, 10],
+        vec![1.1, 0.000_001],
+        input.selling_start_time,
+        input.selling_end_time,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    );
+    let show = contract.show_metadata("show".to_string()).unwrap();
+    assert_eq!(show.ticket_infos["vip"].price, U128(1_100_000_000_000_000_000_000_000));
+    assert_eq!(show.ticket_infos["ga"].price, U128(1_000_000_000_000_000_000));
+}
+
+#[test]
+#[should_panic(expected = "ticket_prices[0]: Ticket price must be a non negative amount of NEAR")]
+fn negative_near_prices_are_rejected() {
+    let mut contract = setup();
+    act_as(accounts(1));
+    contract.create_new_show_near_prices(
+        "show".to_string(),
+        None,
+        None,
+        0,
+        None,
+        vec!["vip".to_string()],
+        vec![10],
+        vec![-1.0],
+        0,
+        1000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    );
+}
+
+#[test]
+fn show_arrays_must_have_matching_lengths() {
+    let contract = setup();
+    let mut input = show_input("show");
+    input.tickets_supply = vec![10, 10];
+    let errors = contract.validate_show_config(input);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].field, "ticket_types");
+    let mut input = show_input("show");
+    input.ticket_types = vec![];
+    input.tickets_supply = vec![];
+    input.ticket_prices = vec![];
+    let errors = contract.validate_show_config(input);
+    assert_eq!(errors[0].message, "At least one ticket type is required");
+    assert!(contract.validate_show_config(show_input("show")).is_empty());
+}
+
+#[test]
+#[should_panic(expected = "tickets_supply and ticket_prices must have the same length")]
+fn creating_a_show_with_mismatched_arrays_fails() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.ticket_prices = vec![];
+    create(&mut contract, input);
+}
+
+#[test]
+#[should_panic(expected = "ticket_types: At least one ticket type is required")]
+fn creating_a_show_without_types_fails() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.ticket_types = vec![];
+    input.tickets_supply = vec![];
+    input.ticket_prices = vec![];
+    create(&mut contract, input);
+}
+
+#[test]
+#[should_panic(expected = "ticket_types[1]: Duplicate ticket type vip")]
+fn creating_a_show_with_duplicate_types_fails() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.ticket_types = vec!["vip".to_string(), "vip".to_string()];
+    input.tickets_supply = vec![10, 10];
+    input.ticket_prices = vec![U128(ONE_NEAR), U128(ONE_NEAR)];
+    create(&mut contract, input);
+}
+
+#[test]
+fn usd_priced_types_are_sold_at_the_oracle_rate() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.ticket_usd_prices = Some(vec![Some(500)]);
+    create(&mut contract, input);
+    let show = contract.show_metadata("show".to_string()).unwrap();
+    assert_eq!(show.ticket_infos["vip"].usd_cents, Some(500));
+    contract.set_price_oracle(accounts(5), U128(1), U128(ONE_NEAR));
+    let rate = ONE_NEAR / 1000;
+    testing_env!(
+        context(accounts(0)).build(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        vec![PromiseResult::Successful(serde_json::to_vec(&U128(rate)).unwrap())]
+    );
+    let deposit = contract.required_deposit(500 * rate);
+    contract.on_usd_rate_for_purchase(
+        accounts(2).into(),
+        "show".to_string(),
+        "vip".to_string(),
+        U128(deposit),
+    );
+    assert_eq!(contract.get_usd_rate(), Some(U128(rate)));
+    let breakdown = contract.ticket_price_breakdown("show".to_string(), "vip".to_string());
+    assert_eq!(breakdown.ticket_price, U128(500 * rate));
+}
+
+#[test]
+#[should_panic(expected = "ticket_usd_prices must have the same length as ticket_types")]
+fn creating_a_show_with_mismatched_usd_prices_fails() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.ticket_usd_prices = Some(vec![Some(500), None]);
+    create(&mut contract, input);
+}
+
+#[test]
+#[should_panic(expected = "ticket_usd_prices[0]: USD price must be greater than 0 cents")]
+fn creating_a_show_with_a_zero_usd_price_fails() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.ticket_usd_prices = Some(vec![Some(0)]);
+    create(&mut contract, input);
+}
+
+#[test]
+fn retrying_a_creation_after_edits_keeps_the_show() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    buy(&mut contract, accounts(2), "show", "vip");
+    testing_env!(context(accounts(1)).build());
+    contract.set_max_per_account("show".to_string(), Some(2));
+    contract.set_show_categories("show".to_string(), vec!["music".to_string()]);
+    create(&mut contract, show_input("show"));
+    let show = contract.show_metadata("show".to_string()).unwrap();
+    assert_eq!(show.ticket_infos["vip"].sold, 1);
+    assert_eq!(show.max_per_account, Some(2));
+    assert_eq!(show.categories, vec!["music".to_string()]);
+}
+
+#[test]
+#[should_panic(expected = "show_id: This show exist")]
+fn creating_another_show_with_an_existing_id_fails() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let mut input = show_input("show");
+    input.tickets_supply = vec![20];
+    create(&mut contract, input);
+}
+
+#[test]
+#[should_panic(expected = "show_id: Show show was cancelled")]
+fn retrying_the_creation_of_a_cancelled_show_fails() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    contract.cancel_show("show".to_string());
+    create(&mut contract, show_input("show"));
+}
+
+#[test]
+fn ticket_types_sell_in_their_own_window() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.ticket_types = vec!["vip".to_string(), "ga".to_string()];
+    input.tickets_supply = vec![10, 10];
+    input.ticket_prices = vec![U128(ONE_NEAR), U128(ONE_NEAR)];
+    input.selling_start_time = 200;
+    input.ticket_selling_windows = Some(vec![(Some(50), None), (None, None)]);
+    create(&mut contract, input);
+    buy(&mut contract, accounts(2), "show", "vip");
+}
+
+#[test]
+#[should_panic(expected = "This show has not started selling tickets yet 200")]
+fn types_without_a_window_use_the_show_window() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.selling_start_time = 200;
+    create(&mut contract, input);
+    buy(&mut contract, accounts(2), "show", "vip");
+}
+
+#[test]
+#[should_panic(expected = "This show has ended ticket sales 90")]
+fn type_windows_close_before_the_show_window() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.ticket_selling_windows = Some(vec![(None, Some(90))]);
+    create(&mut contract, input);
+    buy(&mut contract, accounts(2), "show", "vip");
+}
+
+#[test]
+#[should_panic(expected = "An account can buy at most 2 tickets of this show, you bought 2")]
+fn accounts_can_buy_up_to_the_limit() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_max_per_account("show".to_string(), Some(2));
+    buy(&mut contract, accounts(2), "show", "vip");
+    buy(&mut contract, accounts(2), "show", "vip");
+    assert_eq!(contract.purchases_by_account("show".to_string(), accounts(2).into()), 2);
+    buy(&mut contract, accounts(3), "show", "vip");
+    buy(&mut contract, accounts(2), "show", "vip");
+}
+
+#[test]
+fn allowlisted_accounts_buy_during_the_presale() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_presale_end_time("show".to_string(), Some(500));
+    let accounts_added = vec![accounts(2).into(), accounts(2).into(), accounts(3).into()];
+    assert_eq!(contract.add_to_allowlist("show".to_string(), accounts_added), 2);
+    assert_eq!(contract.presale_size("show".to_string()), 2);
+    assert!(contract.is_allowlisted("show".to_string(), accounts(2).into()));
+    buy(&mut contract, accounts(2), "show", "vip");
+    act_as(accounts(1));
+    contract.remove_from_allowlist("show".to_string(), vec![accounts(3).into()]);
+    assert_eq!(contract.presale_size("show".to_string()), 1);
+}
+
+#[test]
+#[should_panic(expected = "Only allowlisted accounts can buy tickets before 500")]
+fn others_wait_for_the_end_of_the_presale() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_presale_end_time("show".to_string(), Some(500));
+    contract.add_to_allowlist("show".to_string(), vec![accounts(2).into()]);
+    buy(&mut contract, accounts(3), "show", "vip");
+}
+
+#[test]
+fn used_tickets_are_burned_and_their_storage_refunded() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    check(&mut contract, accounts(2), &ticket_id);
+    testing_env!(context(accounts(2)).attached_deposit(1).build());
+    contract.burn_ticket(ticket_id.clone());
+    assert!(contract.ticket_metadata(ticket_id.clone()).is_none());
+    assert!(contract.tokens.owner_by_id.get(&ticket_id).is_none());
+    let refund = transfers()[0].clone();
+    assert_eq!(refund.0, AccountId::from(accounts(2)));
+    assert!(refund.1 > 1);
+    assert!(get_logs()[0].contains(r#""event":"nft_burn""#));
+    // the burned ticket stays sold
+    assert_eq!(contract.remaining_tickets("show".to_string(), "vip".to_string()), 9);
+}
+
+#[test]
+#[should_panic(expected = "can be burned once it is used or the ticket sales ended")]
+fn unused_tickets_can_not_be_burned_during_sales() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(1).build());
+    contract.burn_ticket(ticket_id);
+}
+
+#[test]
+fn burned_tickets_leave_no_token_listing_or_metadata() {
+    let mut contract = setup();
+    let ticket_id = resale_show(&mut contract);
+    list(&mut contract, accounts(2), &ticket_id, ONE_NEAR);
+    testing_env!(context(accounts(2)).attached_deposit(1).block_timestamp(1001).build());
+    contract.burn_ticket(ticket_id.clone());
+    assert!(contract.tickets.get(&ticket_id).is_none());
+    assert_eq!(contract.get_resale_listing(ticket_id), None);
+    assert_eq!(contract.tokens.owner_by_id.len(), 0);
+    assert!(contract.nft_tokens_for_owner(accounts(2), None, None).is_empty());
+}
+
+#[test]
+fn ownership_is_transferred_in_two_steps() {
+    let mut contract = setup();
+    act_as(accounts(1));
+    contract.transfer_ownership(accounts(2));
+    assert_eq!(contract.get_pending_owner(), Some(accounts(2).into()));
+    // the current owner stays in charge until the transfer is accepted
+    contract.set_max_shows(Some(3));
+    act_as(accounts(2));
+    contract.accept_ownership();
+    assert_eq!(contract.get_pending_owner(), None);
+    contract.set_max_shows(Some(4));
+    assert_eq!(contract.get_max_shows(), Some(4));
+}
+
+#[test]
+#[should_panic(expected = "Caller danny is not the pending owner")]
+fn only_the_pending_owner_accepts_the_ownership() {
+    let mut contract = setup();
+    act_as(accounts(1));
+    contract.transfer_ownership(accounts(2));
+    act_as(accounts(3));
+    contract.accept_ownership();
+}
+
+#[test]
+#[should_panic(expected = "Ownership renounced, the contract has no owner")]
+fn an_ownerless_contract_takes_no_owner_action() {
+    let mut contract = setup();
+    act_as(accounts(1));
+    contract.transfer_ownership(accounts(2));
+    contract.renounce_ownership();
+    assert!(contract.is_ownerless());
+    assert_eq!(contract.get_pending_owner(), None);
+    create(&mut contract, show_input("show"));
+}
+
+fn transfer(
+    contract: &mut Contract,
+    holder: ValidAccountId,
+    receiver: ValidAccountId,
+    ticket_id: &str,
+) {
+    testing_env!(context(holder).attached_deposit(1).build());
+    contract.nft_transfer(receiver, ticket_id.to_string(), None, None);
+}
+
+#[test]
+fn ticket_status_follows_the_ticket() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    assert_eq!(contract.ticket_status("show.vip.9".to_string()), TicketStatus::Unknown);
+    assert!(contract.is_ticket_valid(ticket_id.clone()));
+    act_at(accounts(2), 1001);
+    assert_eq!(contract.ticket_status(ticket_id.clone()), TicketStatus::Expired);
+    check(&mut contract, accounts(2), &ticket_id);
+    assert_eq!(contract.ticket_status(ticket_id.clone()), TicketStatus::Used);
+    act_as(accounts(1));
+    contract.cancel_show("show".to_string());
+    assert_eq!(contract.ticket_status(ticket_id.clone()), TicketStatus::ShowCancelled);
+    assert!(!contract.is_ticket_valid(ticket_id));
+}
+
+#[test]
+fn scanners_check_in_tickets_of_their_show() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    act_as(accounts(1));
+    contract.add_scanner("show".to_string(), accounts(4).into());
+    assert_eq!(contract.get_scanners("show".to_string()), vec![AccountId::from(accounts(4))]);
+    act_as(accounts(4));
+    contract.check_ticket_as_staff(ticket_id.clone());
+    assert_eq!(contract.ticket_status(ticket_id), TicketStatus::Used);
+    act_as(accounts(1));
+    contract.remove_scanner("show".to_string(), accounts(4).into());
+    assert!(!contract.is_scanner("show".to_string(), accounts(4).into()));
+}
+
+#[test]
+#[should_panic(expected = "Caller eugene is not a scanner of show show")]
+fn other_accounts_can_not_scan() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    act_as(accounts(4));
+    contract.check_ticket_as_staff(ticket_id);
+}
+
+#[test]
+fn payouts_follow_the_show_royalty() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    let mut royalty = HashMap::new();
+    royalty.insert(accounts(1).into(), 1000);
+    royalty.insert(accounts(5).into(), 500);
+    input.royalty = Some(royalty);
+    create(&mut contract, input);
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    let payout = contract.nft_payout(ticket_id.clone(), U128(10_000), 3).payout;
+    assert_eq!(payout.len(), 3);
+    assert_eq!(payout[&AccountId::from(accounts(1))], U128(1000));
+    assert_eq!(payout[&AccountId::from(accounts(5))], U128(500));
+    assert_eq!(payout[&AccountId::from(accounts(2))], U128(8500));
+    testing_env!(context(accounts(2)).attached_deposit(1).build());
+    let payout =
+        contract.nft_transfer_payout(accounts(3), ticket_id.clone(), None, None, U128(10_000), 3);
+    assert_eq!(payout.payout[&AccountId::from(accounts(2))], U128(8500));
+    assert_eq!(contract.tokens.owner_by_id.get(&ticket_id), Some(accounts(3).into()));
+}
+
+#[test]
+#[should_panic(expected = "Payout has more than 2 receivers")]
+fn payouts_are_bounded_by_max_len_payout() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    let mut royalty = HashMap::new();
+    royalty.insert(accounts(1).into(), 1000);
+    royalty.insert(accounts(5).into(), 500);
+    input.royalty = Some(royalty);
+    create(&mut contract, input);
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    contract.nft_payout(ticket_id, U128(10_000), 2);
+}
+
+#[test]
+fn royalties_above_100_percent_are_rejected() {
+    let contract = setup();
+    let mut input = show_input("show");
+    let mut royalty = HashMap::new();
+    royalty.insert(accounts(1).into(), 6000);
+    royalty.insert(accounts(5).into(), 5000);
+    input.royalty = Some(royalty);
+    assert_eq!(contract.validate_show_config(input)[0].field, "royalty");
+}
+
+#[test]
+fn revenue_is_withdrawn_by_the_splits() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.splits = Some(vec![(accounts(4).into(), 7000), (accounts(5).into(), 3000)]);
+    create(&mut contract, input);
+    buy(&mut contract, accounts(2), "show", "vip");
+    buy(&mut contract, accounts(3), "show", "vip");
+    assert_eq!(contract.get_revenue("show".to_string()), U128(2 * ONE_NEAR));
+    act_as(accounts(1));
+    contract.withdraw_revenue("show".to_string());
+    let mut paid = transfers();
+    paid.sort();
+    assert_eq!(
+        paid,
+        vec![
+            (accounts(1).into(), 0),
+            (accounts(4).into(), 2 * ONE_NEAR * 7 / 10),
+            (accounts(5).into(), 2 * ONE_NEAR * 3 / 10),
+        ]
+    );
+    assert_eq!(contract.get_revenue("show".to_string()), U128(0));
+}
+
+#[test]
+fn splits_must_add_up_to_100_percent() {
+    let contract = setup();
+    let mut input = show_input("show");
+    input.splits = Some(vec![(accounts(4).into(), 7000), (accounts(5).into(), 2000)]);
+    let errors = contract.validate_show_config(input);
+    assert_eq!(errors[0].message, "Splits must add up to 10000 basis points");
+}
+
+#[test]
+#[should_panic(expected = "Show show has no revenue to withdraw")]
+fn revenue_can_only_be_withdrawn_once() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    buy(&mut contract, accounts(2), "show", "vip");
+    act_as(accounts(1));
+    contract.withdraw_revenue("show".to_string());
+    assert_eq!(transfers(), vec![(accounts(1).into(), ONE_NEAR)]);
+    contract.withdraw_revenue("show".to_string());
+}
+
+#[test]
+fn locked_revenue_is_withdrawable_after_the_event() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.event_end_time = Some(1500);
+    create(&mut contract, input);
+    act_as(accounts(1));
+    contract.set_proceeds_locked_until_event("show".to_string(), true);
+    buy(&mut contract, accounts(2), "show", "vip");
+    let status = contract.revenue_status("show".to_string());
+    assert_eq!((status.locked, status.withdrawable), (U128(ONE_NEAR), U128(0)));
+    act_at(accounts(1), 1501);
+    let status = contract.revenue_status("show".to_string());
+    assert_eq!((status.locked, status.withdrawable), (U128(0), U128(ONE_NEAR)));
+    contract.withdraw_revenue("show".to_string());
+}
+
+#[test]
+#[should_panic(expected = "Revenue of show show is locked until its event ends")]
+fn locked_revenue_is_not_withdrawable_before_the_event() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_proceeds_locked_until_event("show".to_string(), true);
+    buy(&mut contract, accounts(2), "show", "vip");
+    act_as(accounts(1));
+    contract.withdraw_revenue("show".to_string());
+}
+
+#[test]
+fn mint_fee_and_prepare_gas_are_configurable() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_mint_fee(U128(2 * DEFAULT_MINT_FEE));
+    contract.set_prepare_gas(U64(20_000_000_000_000));
+    assert_eq!(contract.get_mint_fee(), U128(2 * DEFAULT_MINT_FEE));
+    assert_eq!(contract.get_prepare_gas(), U64(20_000_000_000_000));
+    assert_eq!(
+        contract.ticket_price_breakdown("show".to_string(), "vip".to_string()).total,
+        U128(ONE_NEAR + 2 * DEFAULT_MINT_FEE + TICKET_STORAGE_DEPOSIT)
+    );
+    buy(&mut contract, accounts(2), "show", "vip");
+    assert_eq!(contract.show_revenue("show".to_string()), U128(ONE_NEAR + 2 * DEFAULT_MINT_FEE));
+}
+
+#[test]
+#[should_panic(expected = "Mint fee must be at least")]
+fn the_mint_fee_covers_the_token_storage() {
+    let mut contract = setup();
+    act_as(accounts(1));
+    contract.set_mint_fee(U128(MIN_MINT_FEE - 1));
+}
+
+#[test]
+#[should_panic(expected = "Prepare gas must be between")]
+fn prepare_gas_leaves_room_for_a_batch_purchase() {
+    let mut contract = setup();
+    act_as(accounts(1));
+    contract.set_prepare_gas(U64(MAX_PREPAID_GAS / MAX_TICKETS_PER_PURCHASE as Gas));
+}
+
+fn ft_show(contract: &mut Contract) {
+    create(contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_show_ft_token("show".to_string(), Some("usdc".to_string()));
+    let mut info = contract.show_metadata("show".to_string()).unwrap().ticket_infos["vip"].clone();
+    info.ft_price = Some(U128(20));
+    contract.edit_ticket_info("show".to_string(), info);
+}
+
+fn ft_buy(contract: &mut Contract, buyer: ValidAccountId, amount: Balance) {
+    act_as("usdc".try_into().unwrap());
+    let msg = r#"{"show_id":"show","ticket_type":"vip"}"#.to_string();
+    contract.ft_on_transfer(buyer, U128(amount), msg);
+}
+
+#[test]
+fn tickets_can_be_bought_with_the_show_token() {
+    let mut contract = setup();
+    ft_show(&mut contract);
+    ft_buy(&mut contract, accounts(2), 25);
+    let minted = private_mint(&mut contract, "show.vip.0", accounts(2));
+    testing_env!(
+        context(accounts(0)).build(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        vec![PromiseResult::Successful(serde_json::to_vec(&minted).unwrap())]
+    );
+    let unused = contract.on_ft_mint(
+        accounts(2).into(),
+        "show".to_string(),
+        "vip".to_string(),
+        U128(25),
+        U128(20),
+    );
+    assert_eq!(unused, U128(5));
+    assert!(get_logs()[0].contains(r#""event":"ticket_purchased""#));
+    assert!(get_logs()[0].contains(r#""price":"20""#));
+    assert_eq!(contract.get_ft_revenue("show".to_string()), U128(20));
+    assert_eq!(contract.get_revenue("show".to_string()), U128(0));
+}
+
+#[test]
+fn failed_token_purchases_return_the_amount() {
+    let mut contract = setup();
+    ft_show(&mut contract);
+    ft_buy(&mut contract, accounts(2), 25);
+    testing_env!(
+        context(accounts(0)).build(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        vec![PromiseResult::Failed]
+    );
+    let unused = contract.on_ft_mint(
+        accounts(2).into(),
+        "show".to_string(),
+        "vip".to_string(),
+        U128(25),
+        U128(20),
+    );
+    assert_eq!(unused, U128(25));
+    assert_eq!(contract.remaining_tickets("show".to_string(), "vip".to_string()), 10);
+}
+
+#[test]
+#[should_panic(expected = "Show show does not accept token dai")]
+fn other_tokens_are_rejected() {
+    let mut contract = setup();
+    ft_show(&mut contract);
+    act_as("dai".try_into().unwrap());
+    let msg = r#"{"show_id":"show","ticket_type":"vip"}"#.to_string();
+    contract.ft_on_transfer(accounts(2), U128(25), msg);
+}
+
+#[test]
+#[should_panic(expected = "Please transfer at least 20. You transfer 19")]
+fn token_purchases_cover_the_price() {
+    let mut contract = setup();
+    ft_show(&mut contract);
+    ft_buy(&mut contract, accounts(2), 19);
+}
+
+#[test]
+fn shows_without_sales_can_be_removed() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.remove_show("show".to_string());
+    assert!(contract.show_metadata("show".to_string()).is_none());
+    assert_eq!(transfers()[0].0, AccountId::from(accounts(1)));
+}
+
+#[test]
+#[should_panic(expected = "Show show has sold tickets, use cancel_show instead")]
+fn shows_with_sales_can_not_be_removed() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    buy(&mut contract, accounts(2), "show", "vip");
+    act_as(accounts(1));
+    contract.remove_show("show".to_string());
+}
+
+#[test]
+fn show_times_can_be_moved() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.selling_start_time = 200;
+    create(&mut contract, input);
+    act_as(accounts(1));
+    contract.update_show_times("show".to_string(), Some(50), None);
+    contract.update_show_times("show".to_string(), None, Some(3000));
+    let show = contract.show_metadata("show".to_string()).unwrap();
+    assert_eq!((show.selling_start_time, show.selling_end_time), (50, 3000));
+}
+
+#[test]
+#[should_panic(expected = "Sales of show show already started, the start can not move earlier")]
+fn started_sales_can_not_start_earlier() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.selling_start_time = 50;
+    create(&mut contract, input);
+    act_as(accounts(1));
+    contract.update_show_times("show".to_string(), Some(20), None);
+}
+
+#[test]
+#[should_panic(expected = "Selling end time must be after the start time")]
+fn sales_must_end_after_they_start() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.update_show_times("show".to_string(), None, Some(0));
+}
+
+#[test]
+fn edits_keep_the_sold_counter() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    buy(&mut contract, accounts(2), "show", "vip");
+    let mut info = contract.show_metadata("show".to_string()).unwrap().ticket_infos["vip"].clone();
+    info.sold = 0;
+    info.supply = 1;
+    act_as(accounts(1));
+    contract.edit_ticket_info("show".to_string(), info);
+    let info = &contract.show_metadata("show".to_string()).unwrap().ticket_infos["vip"];
+    assert_eq!((info.supply, info.sold), (1, 1));
+}
+
+#[test]
+#[should_panic(
+    expected = "Supply can not be lower than the 2 tickets sold, reserved or being minted"
+)]
+fn supply_can_not_go_below_the_tickets_sold() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    buy(&mut contract, accounts(2), "show", "vip");
+    let cost = cost_of(&contract, "show", "vip");
+    testing_env!(context(accounts(3)).attached_deposit(cost).build());
+    contract.buy_ticket("show".to_string(), "vip".to_string());
+    let mut info = contract.show_metadata("show".to_string()).unwrap().ticket_infos["vip"].clone();
+    info.supply = 1;
+    act_as(accounts(1));
+    contract.edit_ticket_info("show".to_string(), info);
+}
+
+#[test]
+#[should_panic(expected = "This ticket is not exist")]
+fn edits_can_not_rename_a_type() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let mut info = contract.show_metadata("show".to_string()).unwrap().ticket_infos["vip"].clone();
+    info.ticket_type = "ga".to_string();
+    act_as(accounts(1));
+    contract.edit_ticket_info("show".to_string(), info);
+}
+
+#[test]
+#[should_panic(expected = "Tickets of show show can not be transferred")]
+fn soulbound_tickets_can_not_be_transferred() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_show_soulbound("show".to_string(), true);
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    transfer(&mut contract, accounts(2), accounts(3), &ticket_id);
+}
+
+#[test]
+fn tickets_are_transferable_until_the_lock() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_transfer_lock_time("show".to_string(), Some(500));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    transfer(&mut contract, accounts(2), accounts(3), &ticket_id);
+    assert_eq!(contract.tokens.owner_by_id.get(&ticket_id), Some(accounts(3).into()));
+    // the lock does not stop the check-in
+    testing_env!(context(accounts(3)).attached_deposit(1).block_timestamp(600).build());
+    contract.check_ticket(ticket_id.clone());
+    assert_eq!(contract.ticket_status(ticket_id), TicketStatus::Used);
+}
+
+#[test]
+#[should_panic(expected = "Tickets of show show can not be transferred since 500")]
+fn tickets_can_not_be_transferred_after_the_lock() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_transfer_lock_time("show".to_string(), Some(500));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(1).block_timestamp(500).build());
+    contract.nft_transfer(accounts(3), ticket_id, None, None);
+}
+
+#[test]
+fn tickets_are_indexed_by_show() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    create(&mut contract, show_input("other"));
+    for _ in 0..3 {
+        buy(&mut contract, accounts(2), "show", "vip");
+    }
+    buy(&mut contract, accounts(2), "other", "vip");
+    let page: Vec<TokenId> = contract
+        .get_tickets_by_show("show".to_string(), 1, 5)
+        .into_iter()
+        .map(|ticket| ticket.ticket_id)
+        .collect();
+    assert_eq!(page, vec!["show.vip.1", "show.vip.2"]);
+    assert_eq!(contract.tickets_sold_for_show("show".to_string()), 3);
+    assert_eq!(contract.tickets_sold_for_show("other".to_string()), 1);
+    let tokens = contract.nft_tokens_for_show("show".to_string(), Some(U128(0)), Some(2));
+    assert_eq!(tokens.len(), 2);
+    assert!(tokens[0].metadata.is_some());
+    assert_eq!(contract.token_ids_for_show("other".to_string(), 0, 10), vec!["other.vip.0"]);
+}
+
+#[test]
+fn shows_are_searched_by_title() {
+    let mut contract = setup();
+    for (show_id, title) in [("a", Some("Jazz Night")), ("b", Some("Rock")), ("c", None)] {
+        let mut input = show_input(show_id);
+        input.show_title = title.map(str::to_string);
+        create(&mut contract, input);
+    }
+    let ids = |shows: Vec<ShowMetadata>| -> Vec<String> {
+        shows.into_iter().map(|show| show.show_id).collect()
+    };
+    assert_eq!(ids(contract.search_shows("jAZZ".to_string(), 0, 10)), vec!["a"]);
+    assert_eq!(ids(contract.search_shows("o".to_string(), 0, 10)), vec!["b"]);
+    assert!(contract.search_shows("opera".to_string(), 0, 10).is_empty());
+}
+
+#[test]
+fn purchases_resume_after_unpausing() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_paused(true);
+    assert!(contract.is_paused());
+    // views stay available
+    assert_eq!(contract.shows_count(), 1);
+    contract.set_paused(false);
+    buy(&mut contract, accounts(2), "show", "vip");
+}
+
+#[test]
+#[should_panic(expected = "contract is paused")]
+fn purchases_fail_while_paused() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_paused(true);
+    buy(&mut contract, accounts(2), "show", "vip");
+}
+
+#[test]
+#[should_panic(expected = "contract is paused")]
+fn check_ins_fail_while_paused() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    act_as(accounts(1));
+    contract.set_paused(true);
+    check(&mut contract, accounts(2), &ticket_id);
+}
+
+#[test]
+fn remaining_tickets_follow_the_sales() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.ticket_types = vec!["vip".to_string(), "ga".to_string()];
+    input.tickets_supply = vec![3, 5];
+    input.ticket_prices = vec![U128(ONE_NEAR), U128(ONE_NEAR)];
+    create(&mut contract, input);
+    buy(&mut contract, accounts(2), "show", "vip");
+    buy(&mut contract, accounts(2), "show", "ga");
+    buy(&mut contract, accounts(3), "show", "ga");
+    assert_eq!(contract.remaining_tickets("show".to_string(), "vip".to_string()), 2);
+    let remaining = contract.remaining_tickets_all("show".to_string());
+    assert_eq!((remaining["vip"], remaining["ga"]), (2, 3));
+}
+
+#[test]
+#[should_panic(expected = "This show not exist")]
+fn remaining_tickets_of_a_missing_show_panic() {
+    let contract = setup();
+    contract.remaining_tickets("show".to_string(), "vip".to_string());
+}
+
+#[test]
+fn hidden_remaining_counts_are_bucketed() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.tickets_supply = vec![60];
+    create(&mut contract, input);
+    act_as(accounts(1));
+    contract.set_hide_exact_remaining("show".to_string(), true);
+    let label = || contract.remaining_tickets_label("show".to_string(), "vip".to_string());
+    assert_eq!(label(), "50-100");
+    assert_eq!(contract.remaining_tickets("show".to_string(), "vip".to_string()), 51);
+    assert_eq!(contract.remaining_tickets_all("show".to_string())["vip"], 51);
+    assert_eq!(contract.remaining_tickets_exact("show".to_string(), "vip".to_string()), 60);
+    let ranges = [(0, (0, "sold out")), (9, (1, "<10")), (50, (10, "10-50")), (101, (101, ">100"))];
+    for (remaining, range) in ranges {
+        assert_eq!(Contract::remaining_range(remaining), range);
+    }
+}
+
+#[test]
+fn sold_out_is_reported_per_type_and_show() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.ticket_types = vec!["vip".to_string(), "ga".to_string()];
+    input.tickets_supply = vec![1, 5];
+    input.ticket_prices = vec![U128(ONE_NEAR), U128(ONE_NEAR)];
+    create(&mut contract, input);
+    buy(&mut contract, accounts(2), "show", "vip");
+    assert!(contract.is_type_sold_out("show".to_string(), "vip".to_string()));
+    assert!(!contract.is_type_sold_out("show".to_string(), "ga".to_string()));
+    assert!(!contract.is_type_sold_out("show".to_string(), "balcony".to_string()));
+    assert!(!contract.is_show_sold_out("show".to_string()));
+    assert_eq!(contract.sold_out_types("show".to_string()), vec!["vip"]);
+}
+
+#[test]
+fn seats_are_booked_once_and_released_on_failure() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let cost = cost_of(&contract, "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(cost).build());
+    contract.buy_ticket_with_seat("show".to_string(), "vip".to_string(), "A1".to_string());
+    testing_env!(
+        context(accounts(0)).build(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        vec![PromiseResult::Failed]
+    );
+    let seat = Some("A1".to_string());
+    let mint_fee = contract.mint_fee;
+    let failed = contract.check_mint(
+        accounts(2).into(),
+        "show".to_string(),
+        "vip".to_string(),
+        cost,
+        ONE_NEAR,
+        mint_fee,
+        seat.clone(),
+    );
+    assert_eq!(failed, None);
+    testing_env!(context(accounts(3)).attached_deposit(cost).build());
+    contract.buy_ticket_with_seat("show".to_string(), "vip".to_string(), "A1".to_string());
+    testing_env!(context(accounts(0)).attached_deposit(mint_fee).build());
+    let price = Some(U128(ONE_NEAR));
+    let minted =
+        contract.nft_private_mint("show.vip.1".to_string(), accounts(3), seat.clone(), price);
+    assert_eq!(contract.ticket_metadata(minted.token.token_id).unwrap().seat, seat);
+}
+
+#[test]
+#[should_panic(expected = "Seat A1 is already taken")]
+fn a_seat_can_not_be_booked_twice() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let cost = cost_of(&contract, "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(cost).build());
+    contract.buy_ticket_with_seat("show".to_string(), "vip".to_string(), "A1".to_string());
+    testing_env!(context(accounts(3)).attached_deposit(cost).build());
+    contract.buy_ticket_with_seat("show".to_string(), "vip".to_string(), "A1".to_string());
+}
+
+fn resale_show(contract: &mut Contract) -> TokenId {
+    let mut input = show_input("show");
+    let mut royalty = HashMap::new();
+    royalty.insert(accounts(1).into(), 1000);
+    input.royalty = Some(royalty);
+    create(contract, input);
+    act_as(accounts(1));
+    contract.set_max_resale_multiplier("show".to_string(), Some(15_000));
+    buy(contract, accounts(2), "show", "vip")
+}
+
+fn list(contract: &mut Contract, holder: ValidAccountId, ticket_id: &str, price: Balance) {
+    testing_env!(context(holder).attached_deposit(1).build());
+    contract.list_for_resale(ticket_id.to_string(), U128(price));
+}
+
+#[test]
+fn resales_pay_the_seller_and_the_royalty() {
+    let mut contract = setup();
+    let ticket_id = resale_show(&mut contract);
+    list(&mut contract, accounts(2), &ticket_id, ONE_NEAR * 3 / 2);
+    testing_env!(context(accounts(3)).attached_deposit(2 * ONE_NEAR).build());
+    contract.buy_resale(ticket_id.clone());
+    let mut paid = transfers();
+    paid.sort();
+    assert_eq!(
+        paid,
+        vec![
+            (accounts(1).into(), ONE_NEAR * 3 / 20),
+            (accounts(2).into(), ONE_NEAR * 3 / 2 - ONE_NEAR * 3 / 20),
+            (accounts(3).into(), ONE_NEAR / 2),
+        ]
+    );
+    assert_eq!(contract.tokens.owner_by_id.get(&ticket_id), Some(accounts(3).into()));
+    assert_eq!(contract.get_resale_listing(ticket_id), None);
+}
+
+#[test]
+#[should_panic(expected = "Resale price must be 1 to 1500000000000000000000000 YoctoNear")]
+fn resale_prices_are_capped() {
+    let mut contract = setup();
+    let ticket_id = resale_show(&mut contract);
+    list(&mut contract, accounts(2), &ticket_id, ONE_NEAR * 3 / 2 + 1);
+}
+
+#[test]
+fn type_markups_override_the_show_multiplier() {
+    let mut contract = setup();
+    let ticket_id = resale_show(&mut contract);
+    act_as(accounts(1));
+    contract.set_max_resale_markup("show".to_string(), "vip".to_string(), Some(1000));
+    assert_eq!(
+        contract.max_resale_price("show".to_string(), "vip".to_string()),
+        Some(U128(ONE_NEAR * 11 / 10))
+    );
+    list(&mut contract, accounts(2), &ticket_id, ONE_NEAR * 11 / 10);
+}
+
+#[test]
+#[should_panic(expected = "is used")]
+fn used_tickets_can_not_be_listed() {
+    let mut contract = setup();
+    let ticket_id = resale_show(&mut contract);
+    check(&mut contract, accounts(2), &ticket_id);
+    list(&mut contract, accounts(2), &ticket_id, ONE_NEAR);
+}
+
+#[test]
+#[should_panic(expected = "Ticket is not listed")]
+fn checking_in_removes_the_listing() {
+    let mut contract = setup();
+    let ticket_id = resale_show(&mut contract);
+    list(&mut contract, accounts(2), &ticket_id, ONE_NEAR);
+    check(&mut contract, accounts(2), &ticket_id);
+    testing_env!(context(accounts(3)).attached_deposit(ONE_NEAR).build());
+    contract.buy_resale(ticket_id);
+}
+
+#[test]
+#[should_panic(expected = "Show show is cancelled")]
+fn listings_of_cancelled_shows_can_not_be_bought() {
+    let mut contract = setup();
+    let ticket_id = resale_show(&mut contract);
+    list(&mut contract, accounts(2), &ticket_id, ONE_NEAR);
+    act_as(accounts(1));
+    contract.cancel_show("show".to_string());
+    testing_env!(context(accounts(3)).attached_deposit(ONE_NEAR).build());
+    contract.buy_resale(ticket_id);
+}
+
+#[test]
+#[should_panic(expected = "expired")]
+fn expired_listings_can_not_be_bought() {
+    let mut contract = setup();
+    let ticket_id = resale_show(&mut contract);
+    list(&mut contract, accounts(2), &ticket_id, ONE_NEAR);
+    testing_env!(context(accounts(3)).attached_deposit(ONE_NEAR).block_timestamp(1001).build());
+    contract.buy_resale(ticket_id);
+}
+
+#[test]
+fn tickets_carry_nft_metadata() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.show_description = Some("Live".to_string());
+    input.metadata_templates = Some(vec![Some(MetadataTemplate {
+        reference: Some("https://seats.example/{ticket_id}.json".to_string()),
+        extra: Some(r#"{"ticket":"{ticket_id}"}"#.to_string()),
+    })]);
+    create(&mut contract, input);
+    act_as(accounts(1));
+    contract.set_show_media("show".to_string(), None, Some("https://media.example/".to_string()));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    let metadata = contract.token_of(&ticket_id).unwrap().metadata.unwrap();
+    assert_eq!(metadata.title.unwrap(), "Show — vip #0");
+    assert_eq!(metadata.description.unwrap(), "Live");
+    assert_eq!(metadata.media.unwrap(), "https://media.example/show.vip.0");
+    assert_eq!(metadata.reference.unwrap(), "https://seats.example/show.vip.0.json");
+    assert_eq!(metadata.extra.unwrap(), r#"{"ticket":"show.vip.0"}"#);
+}
+
+#[test]
+fn tickets_without_a_template_have_no_reference() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    let metadata = contract.token_of(&ticket_id).unwrap().metadata.unwrap();
+    assert_eq!((metadata.reference, metadata.extra), (None, None));
+    assert_eq!(metadata.media.unwrap(), DEFAULT_TICKET_MEDIA);
+}
+
+#[test]
+fn contract_metadata_has_base_uri_and_icon() {
+    let contract = setup();
+    let metadata = contract.nft_metadata();
+    assert_eq!(metadata.spec, "nft-1.0.0");
+    assert_eq!(metadata.base_uri, Some("https://tickets.example".to_string()));
+    assert_eq!(metadata.icon, None);
+}
+
+#[test]
+fn waitlisted_accounts_are_notified_of_new_supply() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.tickets_supply = vec![1];
+    create(&mut contract, input);
+    buy(&mut contract, accounts(2), "show", "vip");
+    for account in [accounts(3), accounts(4)] {
+        testing_env!(context(account).attached_deposit(ONE_NEAR).build());
+        contract.join_waitlist("show".to_string(), "vip".to_string());
+    }
+    let waiting = contract.waitlist("show".to_string(), "vip".to_string(), 0, 10);
+    assert_eq!(waiting, vec![AccountId::from(accounts(3)), accounts(4).into()]);
+    act_as(accounts(1));
+    contract.increase_supply("show".to_string(), "vip".to_string(), 1);
+    let logs = get_logs();
+    assert_eq!(
+        logs[0],
+        r#"EVENT_JSON:{"data":[{"additional":1,"show_id":"show","supply":2,"ticket_type":"vip"}],"event":"supply_increased","standard":"ticket","version":"1.0.0"}"#
+    );
+    assert_eq!(
+        logs[1],
+        r#"EVENT_JSON:{"data":[{"account_id":"danny","show_id":"show","ticket_type":"vip"}],"event":"waitlist_notified","standard":"ticket","version":"1.0.0"}"#
+    );
+    assert_eq!(logs.len(), 2);
+    let waiting = contract.waitlist("show".to_string(), "vip".to_string(), 0, 10);
+    assert_eq!(waiting, vec![AccountId::from(accounts(4))]);
+    let info = &contract.show_metadata("show".to_string()).unwrap().ticket_infos["vip"];
+    assert_eq!((info.supply, info.sold), (2, 1));
+    buy(&mut contract, accounts(3), "show", "vip");
+}
+
+#[test]
+#[should_panic(expected = "Ticket type vip is not sold out")]
+fn only_sold_out_types_have_a_waitlist() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    testing_env!(context(accounts(3)).attached_deposit(ONE_NEAR).build());
+    contract.join_waitlist("show".to_string(), "vip".to_string());
+}
+
+#[test]
+fn burning_does_not_notify_the_waitlist() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.tickets_supply = vec![1];
+    create(&mut contract, input);
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    testing_env!(context(accounts(3)).attached_deposit(ONE_NEAR).build());
+    contract.join_waitlist("show".to_string(), "vip".to_string());
+    check(&mut contract, accounts(2), &ticket_id);
+    testing_env!(context(accounts(2)).attached_deposit(1).build());
+    contract.burn_ticket(ticket_id);
+    assert!(get_logs().iter().all(|log| !log.contains("waitlist_notified")));
+}
+
+#[test]
+fn tickets_are_returned_within_the_refund_window() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_refund_window("show".to_string(), Some(50));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(1).block_timestamp(NOW + 50).build());
+    contract.refund_ticket(ticket_id.clone());
+    let refund = transfers()[0].clone();
+    assert_eq!(refund.0, AccountId::from(accounts(2)));
+    assert!(refund.1 > ONE_NEAR);
+    assert!(contract.ticket_metadata(ticket_id).is_none());
+    assert_eq!(contract.remaining_tickets("show".to_string(), "vip".to_string()), 10);
+    assert_eq!(contract.get_revenue("show".to_string()), U128(0));
+}
+
+#[test]
+#[should_panic(expected = "Refund window of ticket show.vip.0 is over")]
+fn tickets_are_not_returned_after_the_window() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_refund_window("show".to_string(), Some(50));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(1).block_timestamp(NOW + 51).build());
+    contract.refund_ticket(ticket_id);
+}
+
+#[test]
+#[should_panic(expected = "Ticket show.vip.0 is used")]
+fn used_tickets_are_not_returned() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_refund_window("show".to_string(), Some(50));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    check(&mut contract, accounts(2), &ticket_id);
+    testing_env!(context(accounts(2)).attached_deposit(1).build());
+    contract.refund_ticket(ticket_id);
+}
+
+#[test]
+fn returned_tickets_do_not_count_against_the_account_limit() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_refund_window("show".to_string(), Some(50));
+    contract.set_max_per_account("show".to_string(), Some(1));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(1).build());
+    contract.refund_ticket(ticket_id);
+    assert_eq!(contract.purchases_by_account("show".to_string(), accounts(2).into()), 0);
+    buy(&mut contract, accounts(2), "show", "vip");
+}
+
+#[test]
+fn revenue_is_locked_while_tickets_can_be_returned() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_refund_window("show".to_string(), Some(50));
+    buy(&mut contract, accounts(2), "show", "vip");
+    let status = contract.revenue_status("show".to_string());
+    assert_eq!((status.locked, status.withdrawable), (U128(ONE_NEAR), U128(0)));
+    act_at(accounts(1), 1051);
+    let status = contract.revenue_status("show".to_string());
+    assert_eq!((status.locked, status.withdrawable), (U128(0), U128(ONE_NEAR)));
+    contract.withdraw_revenue("show".to_string());
+}
+
+#[test]
+#[should_panic(expected = "Tickets of show show can be returned until 1050")]
+fn revenue_is_not_withdrawn_during_the_refund_window() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    act_as(accounts(1));
+    contract.set_refund_window("show".to_string(), Some(50));
+    buy(&mut contract, accounts(2), "show", "vip");
+    act_at(accounts(1), 1050);
+    contract.withdraw_revenue("show".to_string());
+}
+
+#[test]
+#[should_panic(
+    expected = "Revenue of show show was withdrawn, ticket show.vip.0 can not be returned"
+)]
+fn tickets_are_not_returned_from_withdrawn_revenue() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    act_as(accounts(1));
+    contract.withdraw_revenue("show".to_string());
+    contract.set_refund_window("show".to_string(), Some(50));
+    testing_env!(context(accounts(2)).attached_deposit(1).build());
+    contract.refund_ticket(ticket_id);
+}
+
+#[test]
+#[should_panic(expected = "show missing does not exist")]
+fn buying_from_a_missing_show_fails_clearly() {
+    let mut contract = setup();
+    testing_env!(context(accounts(2)).attached_deposit(2 * ONE_NEAR).build());
+    contract.buy_ticket("missing".to_string(), "vip".to_string());
+}
+
+#[test]
+#[should_panic(expected = "ticket type balcony does not exist for show show")]
+fn buying_a_missing_type_fails_clearly() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    testing_env!(context(accounts(2)).attached_deposit(2 * ONE_NEAR).build());
+    contract.buy_ticket("show".to_string(), "balcony".to_string());
+}
+
+#[test]
+#[should_panic(expected = "Ticket show.vip.0 already used")]
+fn a_ticket_is_checked_once() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    check(&mut contract, accounts(2), &ticket_id);
+    check(&mut contract, accounts(2), &ticket_id);
+}
+
+#[test]
+fn batch_check_ins_report_each_ticket() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let first = buy(&mut contract, accounts(2), "show", "vip");
+    let second = buy(&mut contract, accounts(2), "show", "vip");
+    let others = buy(&mut contract, accounts(3), "show", "vip");
+    check(&mut contract, accounts(2), &second);
+    testing_env!(context(accounts(2)).attached_deposit(1).build());
+    let ticket_ids = vec![first.clone(), second.clone(), others.clone(), "show.vip.9".to_string()];
+    let results = contract.check_tickets(ticket_ids);
+    assert_eq!(
+        results,
+        vec![
+            (first, true),
+            (second, false),
+            (others.clone(), false),
+            ("show.vip.9".to_string(), false)
+        ]
+    );
+    act_as(accounts(1));
+    contract.add_scanner("show".to_string(), accounts(4).into());
+    testing_env!(context(accounts(4)).attached_deposit(1).build());
+    assert_eq!(contract.check_tickets(vec![others.clone()]), vec![(others, true)]);
+}
+
+#[test]
+fn counts_are_kept_for_quick_stats() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    create(&mut contract, show_input("other"));
+    let first = buy(&mut contract, accounts(2), "show", "vip");
+    buy(&mut contract, accounts(2), "other", "vip");
+    buy(&mut contract, accounts(3), "show", "vip");
+    check(&mut contract, accounts(2), &first);
+    act_as(accounts(1));
+    contract.uncheck_ticket(first.clone(), "scanner failure".to_string());
+    check(&mut contract, accounts(2), &first);
+    assert_eq!(contract.shows_count(), 2);
+    assert_eq!(contract.total_tickets_minted(), 3);
+    assert_eq!(contract.total_tickets_checked(), 1);
+}
+
+#[test]
+fn account_summaries_cover_every_show() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    create(&mut contract, show_input("other"));
+    let first = buy(&mut contract, accounts(2), "show", "vip");
+    buy(&mut contract, accounts(2), "show", "vip");
+    buy(&mut contract, accounts(2), "other", "vip");
+    check(&mut contract, accounts(2), &first);
+    let mut summary = contract.account_summary(accounts(2).into());
+    summary.show_ids.sort();
+    assert_eq!(
+        summary,
+        AccountSummary {
+            tickets_held: 3,
+            tickets_used: 1,
+            show_ids: vec!["other".to_string(), "show".to_string()],
+        }
+    );
+    assert_eq!(contract.account_summary(accounts(3).into()).tickets_held, 0);
+}
+
+#[test]
+#[should_panic(expected = "Ticket show.vip.0 is already minted")]
+fn a_ticket_is_minted_once() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    buy(&mut contract, accounts(2), "show", "vip");
+    private_mint(&mut contract, "show.vip.0", accounts(2));
+}
+
+#[test]
+fn ids_with_dots_are_rejected() {
+    let contract = setup();
+    let errors = contract.validate_show_config(show_input("my.show"));
+    assert_eq!(errors[0], ValidationError::new("show_id", "Must not contain '.'".to_string()));
+    let mut input = show_input("show");
+    input.ticket_types = vec!["v.ip".to_string()];
+    let errors = contract.validate_show_config(input);
+    assert_eq!(errors[0].field, "ticket_types[0]");
+}
+
+#[test]
+#[should_panic(expected = "ticket_type: Must not contain '.'")]
+fn added_types_with_dots_are_rejected() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let mut info = contract.show_metadata("show".to_string()).unwrap().ticket_infos["vip"].clone();
+    info.ticket_type = "v.ip".to_string();
+    act_as(accounts(1));
+    contract.add_ticket_info("show".to_string(), info);
+}
+
+#[test]
+fn tickets_stay_valid_until_the_event_ends() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.event_start_time = Some(1200);
+    input.event_end_time = Some(1500);
+    create(&mut contract, input);
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    act_at(accounts(2), 1400);
+    assert!(contract.is_ticket_valid(ticket_id.clone()));
+    testing_env!(context(accounts(2)).attached_deposit(1).block_timestamp(1500).build());
+    contract.check_ticket(ticket_id.clone());
+    assert_eq!(contract.ticket_status(ticket_id), TicketStatus::Used);
+}
+
+#[test]
+#[should_panic(expected = "ticket expired")]
+fn tickets_expire_after_the_event() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.event_end_time = Some(1500);
+    create(&mut contract, input);
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(1).block_timestamp(1501).build());
+    contract.check_ticket(ticket_id);
+}
+
+#[test]
+#[should_panic(expected = "ticket expired")]
+fn tickets_without_event_times_expire_with_the_sales() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(1).block_timestamp(1001).build());
+    contract.check_ticket(ticket_id);
+}
+
+#[test]
+fn type_prices_change_across_shows() {
+    let mut contract = setup();
+    create(&mut contract, show_input("a"));
+    create(&mut contract, show_input("b"));
+    let mut input = show_input("c");
+    input.ticket_types = vec!["ga".to_string()];
+    create(&mut contract, input);
+    create(&mut contract, show_input("d"));
+    act_as(accounts(1));
+    contract.cancel_show("d".to_string());
+    let show_ids = ["a", "b", "c", "d", "missing"].iter().map(|id| id.to_string()).collect();
+    let updated =
+        contract.set_type_price_across_shows(show_ids, "vip".to_string(), U128(2 * ONE_NEAR));
+    assert_eq!(updated, vec!["a", "b"]);
+    assert_eq!(price_of(&contract, "b", "vip"), 2 * ONE_NEAR);
+    assert_eq!(price_of(&contract, "c", "ga"), ONE_NEAR);
+    assert_eq!(price_of(&contract, "d", "vip"), ONE_NEAR);
+}
+
+#[test]
+#[should_panic(expected = "ticket show.vip.0 does not exist")]
+fn checking_a_missing_ticket_fails_clearly() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    check(&mut contract, accounts(2), "show.vip.0");
+}
+
+#[test]
+#[should_panic(expected = "You do not own the ticket show.vip.0")]
+fn only_the_holder_checks_in_a_ticket() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    check(&mut contract, accounts(3), &ticket_id);
+}
+
+#[test]
+fn merged_shows_move_their_tickets_and_revenue() {
+    let mut contract = setup();
+    create(&mut contract, show_input("source"));
+    create(&mut contract, show_input("target"));
+    let first = buy(&mut contract, accounts(2), "source", "vip");
+    buy(&mut contract, accounts(3), "source", "vip");
+    buy(&mut contract, accounts(2), "target", "vip");
+    act_as(accounts(1));
+    assert!(!contract.merge_shows("source".to_string(), "target".to_string(), 1));
+    assert!(contract.show_metadata("source".to_string()).is_none());
+    assert!(contract.merge_shows("source".to_string(), "target".to_string(), 1));
+    assert_eq!(
+        get_logs().last().unwrap(),
+        r#"EVENT_JSON:{"data":[{"source_id":"source","target_id":"target","tickets":2}],"event":"show_merged","standard":"ticket","version":"1.0.0"}"#
+    );
+    let target = contract.show_metadata("target".to_string()).unwrap();
+    assert_eq!((target.ticket_infos["vip"].supply, target.ticket_infos["vip"].sold), (20, 3));
+    assert_eq!(contract.get_revenue("target".to_string()), U128(3 * ONE_NEAR));
+    assert_eq!(contract.get_revenue("source".to_string()), U128(0));
+    assert_eq!(contract.purchases_by_account("target".to_string(), accounts(2).into()), 2);
+    assert_eq!(contract.token_ids_for_show("target".to_string(), 0, 10).len(), 3);
+    assert_eq!(contract.ticket_metadata(first.clone()).unwrap().show_id, "target");
+    check(&mut contract, accounts(2), &first);
+}
+
+#[test]
+#[should_panic(expected = "being minted")]
+fn shows_with_pending_mints_are_not_merged() {
+    let mut contract = setup();
+    create(&mut contract, show_input("source"));
+    create(&mut contract, show_input("target"));
+    let cost = cost_of(&contract, "source", "vip");
+    testing_env!(context(accounts(2)).attached_deposit(cost).build());
+    contract.buy_ticket("source".to_string(), "vip".to_string());
+    act_as(accounts(1));
+    contract.merge_shows("source".to_string(), "target".to_string(), 10);
+}
+
+#[test]
+#[should_panic(expected = "Show source is being merged into another show")]
+fn merged_shows_can_not_be_recreated_during_the_merge() {
+    let mut contract = setup();
+    create(&mut contract, show_input("source"));
+    create(&mut contract, show_input("target"));
+    buy(&mut contract, accounts(2), "source", "vip");
+    buy(&mut contract, accounts(2), "source", "vip");
+    act_as(accounts(1));
+    contract.merge_shows("source".to_string(), "target".to_string(), 1);
+    create(&mut contract, show_input("source"));
+}
+
+#[test]
+fn failed_block_mints_are_retried_and_refunded() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let deposit = DEFAULT_MINT_FEE + TICKET_STORAGE_DEPOSIT;
+    testing_env!(context(accounts(1)).attached_deposit(deposit * 3).build());
+    contract.allocate_block("show".to_string(), "vip".to_string(), 3, accounts(4));
+    assert_eq!(contract.remaining_tickets("show".to_string(), "vip".to_string()), 7);
+    testing_env!(context(accounts(0)).attached_deposit(DEFAULT_MINT_FEE).build());
+    contract.nft_block_mint(0, 0);
+    contract.nft_block_mint(0, 2);
+    testing_env!(
+        context(accounts(0)).build(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        vec![PromiseResult::Failed]
+    );
+    assert!(!contract.on_block_mint(0, 1, accounts(1).into(), U128(DEFAULT_MINT_FEE)));
+    assert_eq!(transfers(), vec![(accounts(1).into(), deposit)]);
+    let block = contract.get_block_allocation(0).unwrap();
+    assert_eq!((block.minted, block.failed_serials.clone()), (2, vec![1]));
+    // the failed serial stays reserved
+    assert_eq!(contract.remaining_tickets("show".to_string(), "vip".to_string()), 7);
+    testing_env!(context(accounts(1)).attached_deposit(deposit).build());
+    contract.continue_block_allocation(0);
+    testing_env!(context(accounts(0)).attached_deposit(DEFAULT_MINT_FEE).build());
+    contract.nft_block_mint(0, 1);
+    assert_eq!(contract.tickets_count_by_owner(accounts(4).into()), 3);
+    assert_eq!(contract.remaining_tickets("show".to_string(), "vip".to_string()), 7);
+    assert!(contract.get_block_allocation(0).unwrap().failed_serials.is_empty());
+}
+
+#[test]
+fn reissued_tickets_keep_their_check_in_state() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    check(&mut contract, accounts(2), &ticket_id);
+    testing_env!(context(accounts(0)).attached_deposit(DEFAULT_MINT_FEE).build());
+    let token = contract.nft_reissue_mint(ticket_id.clone(), accounts(3));
+    assert_eq!(token.token_id, "show.vip.0.reissue");
+    let ticket = contract.ticket_metadata(token.token_id.clone()).unwrap();
+    assert!(ticket.is_used);
+    assert_eq!(ticket.entry_count, 1);
+    assert_eq!(ticket.reissued_from, Some(ticket_id.clone()));
+    assert!(contract.ticket_metadata(ticket_id).is_none());
+    assert_eq!(contract.ticket_status(token.token_id), TicketStatus::Used);
+}
+
+#[test]
+fn cancelled_shows_refund_the_price_paid() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    let first = buy(&mut contract, accounts(2), "show", "vip");
+    buy(&mut contract, accounts(3), "show", "vip");
+    check(&mut contract, accounts(2), &first);
+    act_as(accounts(1));
+    contract.set_type_price_across_shows(vec!["show".to_string()], "vip".to_string(), U128(5));
+    contract.cancel_show("show".to_string());
+    assert!(contract.is_cancelled("show".to_string()));
+    assert!(contract.cancel_show_refund_batch("show".to_string(), 10));
+    // the used ticket is not refunded, the other gets what was paid, not the new price
+    assert_eq!(transfers(), vec![(accounts(3).into(), ONE_NEAR)]);
+    assert_eq!(contract.get_revenue("show".to_string()), U128(ONE_NEAR));
+}
+
+#[test]
+#[should_panic(
+    expected = "Show show is cancelled, tickets can be burned once all holders are refunded"
+)]
+fn tickets_of_cancelled_shows_are_burned_after_the_refunds() {
+    let mut contract = setup();
+    create(&mut contract, show_input("show"));
+    buy(&mut contract, accounts(2), "show", "vip");
+    let ticket_id = buy(&mut contract, accounts(3), "show", "vip");
+    act_as(accounts(1));
+    contract.cancel_show("show".to_string());
+    assert!(!contract.cancel_show_refund_batch("show".to_string(), 1));
+    testing_env!(context(accounts(3)).attached_deposit(1).block_timestamp(1001).build());
+    contract.burn_ticket(ticket_id);
+}
+
+#[test]
+fn used_tickets_become_collectibles_after_the_event() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.event_end_time = Some(1500);
+    create(&mut contract, input);
+    act_as(accounts(1));
+    let collectible = CollectibleMetadata {
+        title: "Souvenir".to_string(),
+        media: "https://media.example/souvenir.png".to_string(),
+    };
+    contract.set_show_collectible("show".to_string(), Some(collectible));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    check(&mut contract, accounts(2), &ticket_id);
+    testing_env!(context(accounts(2)).attached_deposit(ONE_NEAR).block_timestamp(1501).build());
+    contract.convert_to_collectible(ticket_id.clone());
+    let metadata = contract.token_of(&ticket_id).unwrap().metadata.unwrap();
+    assert_eq!(metadata.title.unwrap(), "Souvenir");
+    assert!(contract.ticket_metadata(ticket_id).unwrap().collectible);
+}
+
+#[test]
+#[should_panic(expected = "Tickets can become collectibles after the event ends")]
+fn tickets_do_not_become_collectibles_during_the_event() {
+    let mut contract = setup();
+    let mut input = show_input("show");
+    input.event_end_time = Some(1500);
+    create(&mut contract, input);
+    act_as(accounts(1));
+    let collectible = CollectibleMetadata {
+        title: "Souvenir".to_string(),
+        media: "https://media.example/souvenir.png".to_string(),
+    };
+    contract.set_show_collectible("show".to_string(), Some(collectible));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    check(&mut contract, accounts(2), &ticket_id);
+    testing_env!(context(accounts(2)).attached_deposit(ONE_NEAR).block_timestamp(1200).build());
+    contract.convert_to_collectible(ticket_id);
+}
+
+/// A contract deployed by fargo's factory
+fn factory_setup() -> Contract {
+    let mut contract = setup();
+    contract.factory_id = Some(accounts(5).into());
+    contract
+}
+
+fn delete(contract: &mut Contract, requester: ValidAccountId) {
+    act_as(accounts(5));
+    contract.delete_self(accounts(4).into(), requester.into());
+}
+
+#[test]
+fn empty_contracts_are_deleted_for_their_owner() {
+    let mut contract = factory_setup();
+    delete(&mut contract, accounts(1));
+    let receipts = format!("{:?}", get_created_receipts());
+    assert!(receipts.contains("DeleteAccount"));
+    assert!(receipts.contains(r#"beneficiary_id: "eugene""#));
+}
+
+#[test]
+#[should_panic(expected = "Caller bob is not owner: charlie")]
+fn the_creator_can_not_delete_a_transferred_contract() {
+    let mut contract = factory_setup();
+    act_as(accounts(1));
+    contract.transfer_ownership(accounts(2));
+    act_as(accounts(2));
+    contract.accept_ownership();
+    delete(&mut contract, accounts(1));
+}
+
+#[test]
+#[should_panic(expected = "Only the factory can delete this contract")]
+fn only_the_factory_deletes_the_contract() {
+    let mut contract = factory_setup();
+    act_as(accounts(1));
+    contract.delete_self(accounts(4).into(), accounts(1).into());
+}
+
+#[test]
+#[should_panic(expected = "Contract has tickets, burn them first")]
+fn contracts_with_tickets_are_not_deleted() {
+    let mut contract = factory_setup();
+    create(&mut contract, show_input("show"));
+    buy(&mut contract, accounts(2), "show", "vip");
+    delete(&mut contract, accounts(1));
+}
+
+#[test]
+#[should_panic(expected = "Contract has revenue, withdraw it first")]
+fn contracts_with_revenue_are_not_deleted() {
+    let mut contract = factory_setup();
+    create(&mut contract, show_input("show"));
+    let ticket_id = buy(&mut contract, accounts(2), "show", "vip");
+    check(&mut contract, accounts(2), &ticket_id);
+    testing_env!(context(accounts(2)).attached_deposit(1).build());
+    contract.burn_ticket(ticket_id);
+    delete(&mut contract, accounts(1));
+}
+
+#[test]
+#[should_panic(expected = "Contract owes refunds, pay them first")]
+fn contracts_owing_refunds_are_not_deleted() {
+    let mut contract = factory_setup();
+    contract.total_pending_refunds = 1;
+    delete(&mut contract, accounts(1));
+}
+
+#[test]
+fn contracts_report_the_version_they_were_deployed_with() {
+    assert_eq!(setup().contract_version(), env!("CARGO_PKG_VERSION"));
+    let metadata = setup().ticket_contract_metadata();
+    testing_env!(context(accounts(1)).build());
+    let contract = Contract::new(
+        accounts(1).into(),
+        metadata,
+        Some(accounts(5).into()),
+        None,
+        Some("0.3.0".to_string()),
+    );
+    assert_eq!(contract.contract_version(), "0.3.0");
+}